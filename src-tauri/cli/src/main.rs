@@ -0,0 +1,304 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use tag_anywhere_core::error::AppError;
+use tag_anywhere_core::file_duplicates::find_duplicate_files;
+use tag_anywhere_core::file_hash::{hash_file_with_mode, read_files_and_hash, FileHashResult, HashAlgorithm, HashBatchReport, HashMode};
+use tag_anywhere_core::file_manifest::verify_manifest;
+use tag_anywhere_core::file_operation::delete_unlisted_files;
+use tag_anywhere_core::file_scan::{get_files_with_types, ScanError, ScanOptions};
+use tag_anywhere_core::file_stats::get_stats;
+use tag_anywhere_core::file_thumbnail::{create_thumbnail, create_thumbnail_set, create_thumbnail_with_preview, create_video_storyboard, CoverSelection, PreviewFormat, PreviewOptions, StoryboardOptions, ThumbnailFormat, ThumbnailOptions, ThumbnailProfile};
+use tag_anywhere_core::file_verify::{verify_files, write_verify_report, VerifyItem};
+use tag_anywhere_core::scan_state::{commit_scan_state, scan_for_changes};
+use tag_anywhere_core::thumbnail_cache::cached_thumbnail_set;
+use tag_anywhere_core::tool_resolver::set_resource_dir;
+
+// Every command prints its result as JSON on stdout. Failures print an `AppError` as JSON
+// on stderr and exit with status 1, so scripts can branch on its `code`.
+#[derive(Parser)]
+#[command(name = "tag-anywhere", version, about = "Scan, hash and thumbnail a storehouse without the app")]
+struct Cli {
+    #[arg(long, global = true, help = "Pretty-print the JSON output")]
+    pretty: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "List the media files in a directory")]
+    Scan {
+        dir: String,
+        #[arg(long, help = "Directory of the scan cache. Only the changes since the previous scan are listed")]
+        index_dir: Option<String>,
+        #[command(flatten)]
+        scan: ScanArgs,
+    },
+    #[command(about = "Hash files, and the files in directories")]
+    Hash {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[arg(long, default_value = "sha256", help = "sha256, blake3 or xxh3_128")]
+        algorithm: HashAlgorithm,
+        #[arg(long, default_value = "full", help = "full, or sampled to read only the head, middle and tail")]
+        mode: HashMode,
+        #[command(flatten)]
+        scan: ScanArgs,
+    },
+    #[command(about = "Create the cover thumbnail of an image or video")]
+    Thumb {
+        file: String,
+        #[arg(long)]
+        index_dir: String,
+        #[arg(long, help = "Thumbnail file name without extension [default: the name of the file]")]
+        name: Option<String>,
+        #[arg(long, conflicts_with = "time", help = "Video frame to use")]
+        frame: Option<usize>,
+        #[arg(long, help = "Video time in seconds to use")]
+        time: Option<f64>,
+        #[arg(long, help = "Pick the sharpest well-exposed of frames sampled across a video, falling back to --frame")]
+        smart_cover: bool,
+        #[arg(long = "profile", help = "grid, cover or detail, can be repeated. Prints a profile to path map instead of a single path")]
+        profiles: Vec<ThumbnailProfile>,
+        #[arg(long, help = "jpeg, webp or avif, for profile thumbnails [default: jpeg]")]
+        format: Option<ThumbnailFormat>,
+        #[arg(long, conflicts_with_all = ["profiles", "format", "cached"], help = "Also write an animated preview of a video next to the cover")]
+        preview: bool,
+        #[arg(long, requires = "preview", default_value = "webp", help = "webp or gif")]
+        preview_format: PreviewFormat,
+        #[arg(long, conflicts_with = "name", help = "Reuse profile thumbnails of identical files from the cache in the index directory")]
+        cached: bool,
+    },
+    #[command(about = "Tile frames of a video into a sprite sheet with a WebVTT track for hover scrubbing")]
+    Storyboard {
+        file: String,
+        #[arg(long)]
+        index_dir: String,
+        #[arg(long, help = "File name prefix [default: the name of the file]")]
+        name: Option<String>,
+        #[arg(long, default_value_t = 10.0, help = "Seconds between frames")]
+        interval: f64,
+        #[arg(long, default_value_t = 10)]
+        columns: u32,
+        #[arg(long, default_value_t = 160)]
+        tile_width: u32,
+        #[arg(long, default_value = "jpeg", help = "jpeg, webp or avif")]
+        format: ThumbnailFormat,
+    },
+    #[command(about = "Print the size, type, dimensions and duration of a file")]
+    Stats { file: String },
+    #[command(about = "Find groups of identical files")]
+    Dupes {
+        dir: String,
+        #[arg(long, default_value = "sha256", help = "sha256, blake3 or xxh3_128")]
+        algorithm: HashAlgorithm,
+        #[command(flatten)]
+        scan: ScanArgs,
+    },
+    #[command(about = "Check files against a checksum manifest or a list of expected hashes")]
+    Verify {
+        #[arg(long, required_unless_present = "items", conflicts_with = "items", help = "SHA256SUMS or B3SUMS style manifest")]
+        manifest: Option<String>,
+        #[arg(long, requires = "manifest", help = "Hash algorithm of the manifest [default: from its name]")]
+        algorithm: Option<HashAlgorithm>,
+        #[arg(long, help = "JSON array of { path, expected_hash }, or - for stdin")]
+        items: Option<String>,
+        #[arg(long, requires = "items", help = "Also write the report to this file, as JSON for .json paths")]
+        report: Option<String>,
+    },
+    #[command(about = "Delete the files in a folder that are not listed, e.g. covers of removed files")]
+    Cleanup {
+        folder: String,
+        #[arg(long, help = "File with one path to keep per line, or - for stdin. Must not be empty")]
+        keep: String,
+        #[arg(long, help = "Only print the files that would be deleted")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args)]
+struct ScanArgs {
+    #[arg(long)]
+    max_depth: Option<usize>,
+    #[arg(long, help = "Include hidden files and directories")]
+    hidden: bool,
+    #[arg(long)]
+    follow_symlinks: bool,
+    #[arg(long, default_value_t = 0, help = "Skip files smaller than this many bytes")]
+    min_size: u64,
+    #[arg(long = "include", value_name = "GLOB", help = "Only list files matching a glob, can be repeated")]
+    include_globs: Vec<String>,
+    #[arg(long = "exclude", value_name = "GLOB", help = "Skip paths matching a glob in addition to the defaults, can be repeated")]
+    exclude_globs: Vec<String>,
+}
+
+impl ScanArgs {
+    fn options(self) -> ScanOptions {
+        let mut options = ScanOptions {
+            max_depth: self.max_depth,
+            include_hidden: self.hidden,
+            follow_symlinks: self.follow_symlinks,
+            min_file_size: self.min_size,
+            include_globs: self.include_globs,
+            ..ScanOptions::default()
+        };
+        options.exclude_globs.extend(self.exclude_globs);
+        options
+    }
+}
+
+#[derive(Serialize)]
+struct ThumbnailOutput {
+    thumbnail_path: Option<String>,
+}
+
+fn read_input(path: &str) -> Result<String, AppError> {
+    if path == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        return Ok(input);
+    }
+    fs::read_to_string(path).map_err(|e| AppError::io(Path::new(path), e))
+}
+
+fn file_stem(file: &str) -> Result<String, AppError> {
+    Path::new(file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .ok_or_else(|| AppError::invalid_path(Path::new(file), "Path does not name a file"))
+}
+
+fn print_json<T: Serialize>(value: &T, pretty: bool) -> Result<(), AppError> {
+    let mut stdout = io::stdout().lock();
+    if pretty {
+        serde_json::to_writer_pretty(&mut stdout, value)?;
+    } else {
+        serde_json::to_writer(&mut stdout, value)?;
+    }
+    writeln!(stdout)?;
+    Ok(())
+}
+
+fn hash_paths(paths: Vec<String>, algorithm: HashAlgorithm, mode: HashMode, options: ScanOptions) -> Result<HashBatchReport, AppError> {
+    let mut report = HashBatchReport::default();
+    for path in paths {
+        if Path::new(&path).is_dir() {
+            let dir_report = read_files_and_hash(path, Vec::new(), options.clone(), algorithm, mode)?;
+            report.results.extend(dir_report.results);
+            report.errors.extend(dir_report.errors);
+            continue;
+        }
+        match hash_file_with_mode(Path::new(&path), algorithm, mode) {
+            Ok(hash) => report.results.push(FileHashResult {
+                path,
+                hash,
+                algorithm,
+                mode,
+            }),
+            Err(e) => report.errors.push(ScanError::from_io(Path::new(&path), &e)),
+        }
+    }
+    Ok(report)
+}
+
+fn run(cli: Cli) -> Result<(), AppError> {
+    let pretty = cli.pretty;
+    match cli.command {
+        Command::Scan { dir, index_dir, scan } => match index_dir {
+            Some(index_dir) => {
+                print_json(&scan_for_changes(&dir, &index_dir, &scan.options())?, pretty)?;
+                commit_scan_state(&dir, &index_dir)
+            }
+            None => print_json(&get_files_with_types(&dir, &scan.options())?, pretty),
+        },
+        Command::Hash { paths, algorithm, mode, scan } => print_json(&hash_paths(paths, algorithm, mode, scan.options())?, pretty),
+        Command::Thumb { file, index_dir, name, frame, time, smart_cover, profiles, format, preview, preview_format, cached } => {
+            let cover_selection = if smart_cover { CoverSelection::Smart } else { CoverSelection::Fixed };
+            let options = ThumbnailOptions {
+                profiles,
+                format: format.unwrap_or_default(),
+                cover_selection,
+            };
+            if cached {
+                return print_json(&cached_thumbnail_set(file, index_dir, frame, time, options)?, pretty);
+            }
+            let name = match name {
+                Some(name) => name,
+                None => file_stem(&file)?,
+            };
+            if preview {
+                let preview = PreviewOptions {
+                    format: preview_format,
+                    ..PreviewOptions::default()
+                };
+                return print_json(&create_thumbnail_with_preview(name, file, index_dir, frame, time, cover_selection, preview)?, pretty);
+            }
+            if options.profiles.is_empty() && format.is_none() {
+                let thumbnail_path = create_thumbnail(name, file, index_dir, frame, time, cover_selection)?;
+                return print_json(&ThumbnailOutput { thumbnail_path }, pretty);
+            }
+            print_json(&create_thumbnail_set(name, file, index_dir, frame, time, options)?, pretty)
+        }
+        Command::Storyboard { file, index_dir, name, interval, columns, tile_width, format } => {
+            let name = match name {
+                Some(name) => name,
+                None => file_stem(&file)?,
+            };
+            let options = StoryboardOptions {
+                interval,
+                columns,
+                tile_width,
+                format,
+            };
+            print_json(&create_video_storyboard(name, file, index_dir, options)?, pretty)
+        }
+        Command::Stats { file } => print_json(&get_stats(file)?, pretty),
+        Command::Dupes { dir, algorithm, scan } => {
+            print_json(&find_duplicate_files(dir, scan.options(), algorithm)?, pretty)
+        }
+        Command::Verify { manifest, algorithm, items, report } => {
+            if let Some(manifest) = manifest {
+                return print_json(&verify_manifest(manifest, algorithm)?, pretty);
+            }
+            let input = read_input(items.as_deref().unwrap_or("-"))?;
+            let items: Vec<VerifyItem> =
+                serde_json::from_str(&input).map_err(|e| AppError::invalid_input(format!("Invalid items: {}", e)))?;
+            let verify_report = verify_files(&items)?;
+            if let Some(report) = report {
+                write_verify_report(&verify_report, Path::new(&report))?;
+            }
+            print_json(&verify_report, pretty)
+        }
+        Command::Cleanup { folder, keep, dry_run } => {
+            let keep = read_input(&keep)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect();
+            print_json(&delete_unlisted_files(keep, folder, dry_run)?, pretty)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Lets a `bin` folder next to the executable provide FFmpeg and ImageMagick, as in the app bundle
+    if let Some(dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        set_resource_dir(dir);
+    }
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", serde_json::to_string(&e).unwrap_or_else(|_| e.to_string()));
+            ExitCode::FAILURE
+        }
+    }
+}
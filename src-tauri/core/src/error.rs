@@ -0,0 +1,212 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use image::ImageError;
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+// Serialized as `{ code, message, path, retryable }` plus the tool fields for tool errors.
+// The codes are part of the frontend contract, so variants must not be renamed.
+#[derive(Debug, Clone, Error)]
+pub enum AppError {
+    NotFound { path: Option<PathBuf>, message: String },
+    PermissionDenied { path: Option<PathBuf>, message: String },
+    InvalidPath { path: PathBuf, message: String },
+    InvalidInput { message: String },
+    UnsupportedType { path: Option<PathBuf>, message: String },
+    Decode { path: Option<PathBuf>, message: String },
+    ToolMissing { tool: String, message: String },
+    ToolFailed { tool: String, exit_code: Option<i32>, stderr: String },
+    Io { path: Option<PathBuf>, message: String, retryable: bool },
+    Cancelled,
+    Internal { message: String },
+}
+
+impl AppError {
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        AppError::InvalidInput { message: message.into() }
+    }
+
+    pub fn invalid_path(path: &Path, message: impl Into<String>) -> Self {
+        AppError::InvalidPath {
+            path: path.to_path_buf(),
+            message: message.into(),
+        }
+    }
+
+    pub fn unsupported_type(path: &Path, message: impl Into<String>) -> Self {
+        AppError::UnsupportedType {
+            path: Some(path.to_path_buf()),
+            message: message.into(),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::Internal { message: message.into() }
+    }
+
+    pub fn io(path: &Path, error: io::Error) -> Self {
+        AppError::from(error).with_path(path)
+    }
+
+    // Attaches the path an error is about, unless it already names one
+    pub fn with_path(mut self, file: &Path) -> Self {
+        match &mut self {
+            AppError::NotFound { path, .. }
+            | AppError::PermissionDenied { path, .. }
+            | AppError::UnsupportedType { path, .. }
+            | AppError::Decode { path, .. }
+            | AppError::Io { path, .. }
+                if path.is_none() =>
+            {
+                *path = Some(file.to_path_buf());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NotFound",
+            AppError::PermissionDenied { .. } => "PermissionDenied",
+            AppError::InvalidPath { .. } => "InvalidPath",
+            AppError::InvalidInput { .. } => "InvalidInput",
+            AppError::UnsupportedType { .. } => "UnsupportedType",
+            AppError::Decode { .. } => "Decode",
+            AppError::ToolMissing { .. } => "ToolMissing",
+            AppError::ToolFailed { .. } => "ToolFailed",
+            AppError::Io { .. } => "Io",
+            AppError::Cancelled => "Cancelled",
+            AppError::Internal { .. } => "Internal",
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            AppError::NotFound { path, .. }
+            | AppError::PermissionDenied { path, .. }
+            | AppError::UnsupportedType { path, .. }
+            | AppError::Decode { path, .. }
+            | AppError::Io { path, .. } => path.as_deref(),
+            AppError::InvalidPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    // The description without the path, for reports that list the path separately
+    pub fn message(&self) -> String {
+        match self {
+            AppError::NotFound { message, .. }
+            | AppError::PermissionDenied { message, .. }
+            | AppError::InvalidPath { message, .. }
+            | AppError::InvalidInput { message }
+            | AppError::UnsupportedType { message, .. }
+            | AppError::Decode { message, .. }
+            | AppError::ToolMissing { message, .. }
+            | AppError::Io { message, .. }
+            | AppError::Internal { message } => message.clone(),
+            AppError::ToolFailed { tool, exit_code, stderr } => {
+                let failure = match exit_code {
+                    Some(code) => format!("{} exited with code {}", tool, code),
+                    None => format!("{} was terminated", tool),
+                };
+                if stderr.is_empty() {
+                    failure
+                } else {
+                    format!("{}: {}", failure, stderr)
+                }
+            }
+            AppError::Cancelled => "The operation was cancelled".to_string(),
+        }
+    }
+
+    // Failures that may succeed when simply tried again, e.g. an interrupted read
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AppError::Io { retryable, .. } => *retryable,
+            // Killed by a signal rather than failing on the input
+            AppError::ToolFailed { exit_code, .. } => exit_code.is_none(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path() {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message()),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SerializedAppError<'a> {
+    code: &'static str,
+    message: String,
+    path: Option<&'a Path>,
+    retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<&'a str>,
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (tool, exit_code, stderr) = match self {
+            AppError::ToolMissing { tool, .. } => (Some(tool.as_str()), None, None),
+            AppError::ToolFailed { tool, exit_code, stderr } => (Some(tool.as_str()), *exit_code, Some(stderr.as_str())),
+            _ => (None, None, None),
+        };
+        SerializedAppError {
+            code: self.code(),
+            message: self.to_string(),
+            path: self.path(),
+            retryable: self.is_retryable(),
+            tool,
+            exit_code,
+            stderr,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        let message = error.to_string();
+        match error.kind() {
+            io::ErrorKind::NotFound => AppError::NotFound { path: None, message },
+            io::ErrorKind::PermissionDenied => AppError::PermissionDenied { path: None, message },
+            io::ErrorKind::InvalidInput => AppError::InvalidInput { message },
+            kind => AppError::Io {
+                path: None,
+                message,
+                retryable: matches!(
+                    kind,
+                    io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ),
+            },
+        }
+    }
+}
+
+impl From<ImageError> for AppError {
+    fn from(error: ImageError) -> Self {
+        match error {
+            ImageError::IoError(error) => error.into(),
+            ImageError::Unsupported(_) => AppError::UnsupportedType { path: None, message: error.to_string() },
+            ImageError::Decoding(_) | ImageError::Limits(_) => AppError::Decode { path: None, message: error.to_string() },
+            _ => AppError::internal(error.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        AppError::internal(error.to_string())
+    }
+}
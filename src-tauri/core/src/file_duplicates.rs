@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::error::AppError;
+use crate::file_hash::{hash_file_with_mode, HashAlgorithm, HashMode};
+use crate::file_scan::{ScanError, ScanOptions, StorehouseWalker};
+use crate::scan_state::{FileId, ScanStateEntry};
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub hash: String,
+    pub algorithm: HashAlgorithm,
+    pub paths: Vec<PathBuf>,
+    // Further names of the files in `paths`. They share storage, so deleting them frees nothing.
+    pub hard_links: Vec<PathBuf>,
+    // Bytes freed by keeping a single copy
+    pub reclaimable_bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    pub reclaimable_bytes: u64,
+    pub errors: Vec<ScanError>,
+}
+
+// Splits every candidate group by the given hash, dropping files that turn out to be unique
+fn split_by_hash(
+    groups: Vec<(u64, Vec<PathBuf>)>,
+    algorithm: HashAlgorithm,
+    mode: HashMode,
+    errors: &mut Vec<ScanError>,
+) -> HashMap<(u64, String), Vec<PathBuf>> {
+    let hashes: Vec<(u64, PathBuf, io::Result<String>)> = groups
+        .into_par_iter()
+        .flat_map(|(size, paths)| paths.into_par_iter().map(move |path| (size, path)))
+        .map(|(size, path)| {
+            let hash = hash_file_with_mode(&path, algorithm, mode);
+            (size, path, hash)
+        })
+        .collect();
+
+    let mut by_hash: HashMap<(u64, String), Vec<PathBuf>> = HashMap::new();
+    for (size, path, hash) in hashes {
+        match hash {
+            Ok(hash) => by_hash.entry((size, hash)).or_default().push(path),
+            Err(e) => errors.push(ScanError::from_io(&path, &e)),
+        }
+    }
+
+    by_hash.retain(|_, paths| paths.len() > 1);
+    by_hash
+}
+
+// Files are compared by size first, then by a sampled hash, and only the remaining
+// candidates are read in full, so unique large files are never hashed completely.
+pub fn find_duplicate_files(
+  dir_path: String,
+  options: ScanOptions,
+  algorithm: HashAlgorithm,
+) -> Result<DuplicateReport, AppError> {
+    let mut report = DuplicateReport::default();
+
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    // Hard links are one copy on disk, only the first name found is compared
+    let mut first_links: HashMap<FileId, PathBuf> = HashMap::new();
+    let mut extra_links: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for entry in StorehouseWalker::new(Path::new(&dir_path), &options)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+        match entry.metadata() {
+            // Empty files are all identical but there is nothing to reclaim
+            Ok(metadata) if metadata.len() == 0 => {}
+            Ok(metadata) => {
+                let path = entry.into_path();
+                if let Some(file_id) = ScanStateEntry::from_metadata(&metadata).file_id {
+                    if let Some(first) = first_links.get(&file_id) {
+                        extra_links.entry(first.clone()).or_default().push(path);
+                        continue;
+                    }
+                    first_links.insert(file_id, path.clone());
+                }
+                by_size.entry(metadata.len()).or_default().push(path);
+            }
+            Err(e) => report.errors.push(ScanError::from_walk(&e, entry.path())),
+        }
+    }
+    let candidates = by_size.into_iter().filter(|(_, paths)| paths.len() > 1).collect();
+
+    let sampled = split_by_hash(candidates, algorithm, HashMode::Sampled, &mut report.errors);
+    let sampled = sampled.into_iter().map(|((size, _), paths)| (size, paths)).collect();
+    let confirmed = split_by_hash(sampled, algorithm, HashMode::Full, &mut report.errors);
+
+    for ((size, hash), mut paths) in confirmed {
+        paths.sort();
+        let mut hard_links: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|path| extra_links.remove(path))
+            .flatten()
+            .collect();
+        hard_links.sort();
+        let reclaimable_bytes = size * (paths.len() as u64 - 1);
+        report.reclaimable_bytes += reclaimable_bytes;
+        report.groups.push(DuplicateGroup {
+            size,
+            hash,
+            algorithm,
+            paths,
+            hard_links,
+            reclaimable_bytes,
+        });
+    }
+    report
+        .groups
+        .sort_by(|a, b| b.reclaimable_bytes.cmp(&a.reclaimable_bytes).then_with(|| a.paths.cmp(&b.paths)));
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A directory of files that is removed again when the test ends
+    struct Files {
+        dir: PathBuf,
+    }
+
+    impl Files {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("tag-anywhere-duplicates-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Files { dir }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.join(name)
+        }
+
+        fn write(&self, name: &str, contents: &[u8]) {
+            fs::write(self.path(name), contents).unwrap();
+        }
+
+        fn find(&self) -> DuplicateReport {
+            let report = find_duplicate_files(self.dir.to_string_lossy().to_string(), ScanOptions::default(), HashAlgorithm::Sha256).unwrap();
+            assert!(report.errors.is_empty(), "{:?}", report.errors);
+            report
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn files_of_the_same_size_with_different_contents_are_not_duplicates() {
+        let files = Files::new("same-size");
+        files.write("a.bin", b"contents a");
+        files.write("b.bin", b"contents b");
+
+        assert!(files.find().groups.is_empty());
+    }
+
+    #[test]
+    fn groups_identical_files() {
+        let files = Files::new("identical");
+        files.write("a.bin", b"same contents");
+        files.write("b.bin", b"same contents");
+        files.write("c.bin", b"other contents");
+
+        let report = files.find();
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        assert_eq!(group.paths, vec![files.path("a.bin"), files.path("b.bin")]);
+        assert_eq!((group.size, group.reclaimable_bytes, report.reclaimable_bytes), (13, 13, 13));
+    }
+
+    #[test]
+    fn files_that_only_differ_outside_the_samples_are_not_duplicates() {
+        let files = Files::new("sampled");
+        let mut contents = vec![0u8; 1024 * 1024];
+        files.write("a.bin", &contents);
+        // Between the head and middle chunks, so the sampled hashes still match
+        contents[100 * 1024] = 1;
+        files.write("b.bin", &contents);
+
+        let a = hash_file_with_mode(&files.path("a.bin"), HashAlgorithm::Sha256, HashMode::Sampled).unwrap();
+        let b = hash_file_with_mode(&files.path("b.bin"), HashAlgorithm::Sha256, HashMode::Sampled).unwrap();
+        assert_eq!(a, b);
+        assert!(files.find().groups.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_are_compared_once() {
+        let files = Files::new("hard-links");
+        files.write("a.bin", b"linked contents");
+        fs::hard_link(files.path("a.bin"), files.path("b.bin")).unwrap();
+
+        // The same file under two names frees nothing
+        assert!(files.find().groups.is_empty());
+
+        files.write("c.bin", b"linked contents");
+        let report = files.find();
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        let mut names = group.paths.clone();
+        names.extend(group.hard_links.clone());
+        names.sort();
+        assert_eq!(names, vec![files.path("a.bin"), files.path("b.bin"), files.path("c.bin")]);
+        assert_eq!((group.paths.len(), group.hard_links.len()), (2, 1));
+        assert_eq!(group.reclaimable_bytes, 15);
+    }
+}
@@ -0,0 +1,321 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use ring::digest::{Context, SHA256};
+use data_encoding::HEXLOWER;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::Xxh3;
+use crate::error::AppError;
+use crate::file_scan::{ScanError, ScanOptions, StorehouseWalker};
+
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+const SAMPLE_CHUNK_SIZE: u64 = 64 * 1024;
+
+// Recorded next to every hash so stored values stay comparable when the default changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+    // The 128-bit XXH3 variant, what `xxhsum -H2` prints. Labelled `Xxh3_128` so it is not
+    // mistaken for the 64-bit XXH3 of `xxhsum -H3`; values stored as `Xxh3` still parse.
+    #[serde(rename = "Xxh3_128", alias = "Xxh3")]
+    Xxh3,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "xxh3_128" | "xxh128" | "xxh3" => Ok(HashAlgorithm::Xxh3),
+            _ => Err(AppError::invalid_input(format!("Unknown hash algorithm {}", name))),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    pub fn hex_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Xxh3 => 32,
+        }
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Box::new(Context::new(&SHA256))),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+        }
+    }
+}
+
+// Sampled hashes only read the size and a few chunks, so they are cheap on huge files
+// but cannot prove two files are identical. They are never comparable with full hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum HashMode {
+    #[default]
+    Full,
+    Sampled,
+}
+
+impl FromStr for HashMode {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "full" => Ok(HashMode::Full),
+            "sampled" => Ok(HashMode::Sampled),
+            _ => Err(AppError::invalid_input(format!("Unknown hash mode {}", name))),
+        }
+    }
+}
+
+pub enum Hasher {
+    Sha256(Box<Context>),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(context) => context.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(context) => HEXLOWER.encode(context.finish().as_ref()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileHashResult {
+    pub path: String,
+    pub hash: String,
+    pub algorithm: HashAlgorithm,
+    pub mode: HashMode,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct HashBatchReport {
+    pub results: Vec<FileHashResult>,
+    pub errors: Vec<ScanError>,
+}
+
+// Stored hashes are written as `Algorithm:hex`. Bare hex values predate the prefix and are SHA-256.
+pub fn parse_stored_hash(value: &str) -> Result<(HashAlgorithm, String), AppError> {
+    let (algorithm, hash) = match value.split_once(':') {
+        Some((algorithm, hash)) => (algorithm.parse()?, hash),
+        None => (HashAlgorithm::Sha256, value),
+    };
+    let hash = hash.trim().to_ascii_lowercase();
+    if hash.len() != algorithm.hex_len() || !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(AppError::invalid_input(format!("{} is not a valid {:?} hash", value, algorithm)));
+    }
+    Ok((algorithm, hash))
+}
+
+pub fn hash_file_contents(file: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut reader = File::open(file)?;
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    let mut hasher = algorithm.hasher();
+
+    loop {
+        let len = reader.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..len]);
+    }
+
+    Ok(hasher.finish())
+}
+
+// Hashes the file size plus a chunk from the head, middle and tail.
+// Files too small to sample are hashed whole behind the same size prefix.
+pub fn sample_hash_file_contents(file: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut reader = File::open(file)?;
+    let size = reader.metadata()?.len();
+    let mut hasher = algorithm.hasher();
+    hasher.update(&size.to_le_bytes());
+
+    if size <= SAMPLE_CHUNK_SIZE * 3 {
+        let mut contents = Vec::with_capacity(size as usize);
+        reader.read_to_end(&mut contents)?;
+        hasher.update(&contents);
+        return Ok(hasher.finish());
+    }
+
+    let mut chunk = vec![0; SAMPLE_CHUNK_SIZE as usize];
+    for offset in [0, (size - SAMPLE_CHUNK_SIZE) / 2, size - SAMPLE_CHUNK_SIZE] {
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut chunk)?;
+        hasher.update(&chunk);
+    }
+
+    Ok(hasher.finish())
+}
+
+pub fn hash_file_with_mode(file: &Path, algorithm: HashAlgorithm, mode: HashMode) -> io::Result<String> {
+    match mode {
+        HashMode::Full => hash_file_contents(file, algorithm),
+        HashMode::Sampled => sample_hash_file_contents(file, algorithm),
+    }
+}
+
+pub fn read_and_hash_file(file: &PathBuf, algorithm: HashAlgorithm, mode: HashMode) -> Result<String, AppError> {
+    let md = std::fs::metadata(file).map_err(|e| AppError::io(file, e))?;
+    if md.is_file() {
+        hash_file_with_mode(file, algorithm, mode).map_err(|e| AppError::io(file, e))
+    } else {
+        Err(AppError::invalid_path(file, "Not a file"))
+    }
+}
+
+pub fn read_single_file_and_hash(
+  file_path: String,
+  algorithm: HashAlgorithm,
+  mode: HashMode,
+) -> Result<FileHashResult, AppError> {
+    let path = PathBuf::from(&file_path);
+
+    match read_and_hash_file(&path, algorithm, mode) {
+        Ok(hash) => Ok(FileHashResult {
+            path: file_path.clone(),
+            hash,
+            algorithm,
+            mode,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn read_files_and_hash(
+  dir_path: String,
+  skip_paths: Vec<String>,
+  options: ScanOptions,
+  algorithm: HashAlgorithm,
+  mode: HashMode,
+) -> Result<HashBatchReport, AppError> {
+    let mut report = HashBatchReport::default();
+    let skip_set: std::collections::HashSet<String> = skip_paths.into_iter().collect();
+
+    let mut paths = Vec::new();
+    for entry in StorehouseWalker::new(Path::new(&dir_path), &options)? {
+        match entry {
+            Ok(entry) => {
+                let path = entry.into_path();
+                if !skip_set.contains(path.to_string_lossy().as_ref()) {
+                    paths.push(path);
+                }
+            }
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    let hashes: Vec<(PathBuf, io::Result<String>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let hash = hash_file_with_mode(&path, algorithm, mode);
+            (path, hash)
+        })
+        .collect();
+
+    for (path, hash) in hashes {
+        match hash {
+            Ok(hash) => report.results.push(FileHashResult {
+                path: path.to_string_lossy().to_string(),
+                hash,
+                algorithm,
+                mode,
+            }),
+            Err(e) => report.errors.push(ScanError::from_io(&path, &e)),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Large enough to be sampled rather than hashed whole
+    fn large_contents() -> Vec<u8> {
+        (0..SAMPLE_CHUNK_SIZE as usize * 4 + 1).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn hash_contents(name: &str, contents: &[u8], algorithm: HashAlgorithm, mode: HashMode) -> String {
+        let path = std::env::temp_dir().join(format!("tag-anywhere-hash-{}-{:?}-{:?}-{}", name, algorithm, mode, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let hash = hash_file_with_mode(&path, algorithm, mode);
+        let _ = fs::remove_file(&path);
+        hash.unwrap()
+    }
+
+    #[test]
+    fn full_hashes_match_known_answers() {
+        let cases = [
+            (HashAlgorithm::Sha256, "", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            (HashAlgorithm::Sha256, "abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (HashAlgorithm::Blake3, "", "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+            (HashAlgorithm::Blake3, "abc", "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+            (HashAlgorithm::Xxh3, "", "99aa06d3014798d86001c324468d497f"),
+            (HashAlgorithm::Xxh3, "abc", "06b05ab6733a618578af5f94892f3950"),
+        ];
+        for (algorithm, contents, expected) in cases {
+            assert_eq!(hash_contents("full", contents.as_bytes(), algorithm, HashMode::Full), expected, "{:?} of {:?}", algorithm, contents);
+        }
+
+        let large = large_contents();
+        assert_eq!(hash_contents("full-large", &large, HashAlgorithm::Sha256, HashMode::Full), "6a102a35ef13d267bf487c1520d82b4ff541787bc8c288ccf98aeebe7686016a");
+        assert_eq!(hash_contents("full-large", &large, HashAlgorithm::Blake3, HashMode::Full), "531c319935cf78f34869faebd865e5748266b1799039103bfb851a680d9ed30c");
+        assert_eq!(hash_contents("full-large", &large, HashAlgorithm::Xxh3, HashMode::Full), "9b9f33ce8c9cdfe5092a932e0d783f0c");
+    }
+
+    #[test]
+    fn sampled_hashes_match_known_answers() {
+        // Small files are hashed whole behind their size
+        let cases = [
+            (HashAlgorithm::Sha256, "abc", "ce91dc5eec0139adf091900d225971d6ad246a845bad791b5693a9d0d55dd391"),
+            (HashAlgorithm::Blake3, "abc", "2c43cb52459d810e4b6ce3aab77f69c2925c62316bcfb7d078c93bfdd594ebce"),
+            (HashAlgorithm::Xxh3, "abc", "45bcdc88efffd44520b986a5e7fe1438"),
+        ];
+        for (algorithm, contents, expected) in cases {
+            assert_eq!(hash_contents("sampled", contents.as_bytes(), algorithm, HashMode::Sampled), expected, "{:?} of {:?}", algorithm, contents);
+        }
+
+        // Larger ones only by their size and the head, middle and tail chunks
+        let large = large_contents();
+        assert_eq!(hash_contents("sampled-large", &large, HashAlgorithm::Sha256, HashMode::Sampled), "764c6c8fbe42dcd66f41a110f0bc4e8ff33c38868d30f1b514bd221457a8e59f");
+        assert_eq!(hash_contents("sampled-large", &large, HashAlgorithm::Blake3, HashMode::Sampled), "6e318a6e8f911d2ab9c13a59683ad81d66658f7545f80fe5f591734d4a5537a4");
+        assert_eq!(hash_contents("sampled-large", &large, HashAlgorithm::Xxh3, HashMode::Sampled), "94417fc7e254990111313be66b3d57f0");
+    }
+
+    #[test]
+    fn stored_xxh3_hashes_parse_with_either_label() {
+        let hash = "99aa06d3014798d86001c324468d497f";
+        for label in ["Xxh3_128", "Xxh3"] {
+            let (algorithm, parsed) = parse_stored_hash(&format!("{}:{}", label, hash)).unwrap();
+            assert_eq!((algorithm, parsed.as_str()), (HashAlgorithm::Xxh3, hash));
+        }
+        assert_eq!(serde_json::to_string(&HashAlgorithm::Xxh3).unwrap(), "\"Xxh3_128\"");
+        assert_eq!(serde_json::from_str::<HashAlgorithm>("\"Xxh3\"").unwrap(), HashAlgorithm::Xxh3);
+    }
+}
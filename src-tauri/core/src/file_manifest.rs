@@ -0,0 +1,319 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::error::AppError;
+use crate::file_hash::{hash_file_contents, HashAlgorithm};
+use crate::file_scan::{ScanError, ScanOptions, StorehouseWalker};
+
+// Manifests use the line format of GNU `sha256sum` and `b3sum`: `<hex>  <path>`,
+// with paths relative to the manifest and `/` as separator on every platform
+fn manifest_algorithm(manifest: &Path, algorithm: Option<HashAlgorithm>) -> Result<HashAlgorithm, AppError> {
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => {
+            let name = manifest
+                .file_name()
+                .map(|name| name.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            if name.starts_with("sha256sums") || name.ends_with(".sha256") {
+                HashAlgorithm::Sha256
+            } else if name.starts_with("b3sums") || name.starts_with("blake3sums") || name.ends_with(".b3") {
+                HashAlgorithm::Blake3
+            } else {
+                return Err(AppError::invalid_path(
+                    manifest,
+                    "Cannot tell the hash algorithm from the manifest name. Name it SHA256SUMS or B3SUMS, or pass the algorithm",
+                ));
+            }
+        }
+    };
+
+    // xxhsum writes its own line format, so xxh3 manifests would not be readable by other tools
+    if algorithm == HashAlgorithm::Xxh3 {
+        return Err(AppError::invalid_input("Checksum manifests support SHA-256 and BLAKE3 only"));
+    }
+    Ok(algorithm)
+}
+
+fn manifest_dir(manifest: &Path) -> &Path {
+    manifest.parent().unwrap_or(Path::new(""))
+}
+
+fn manifest_path_of(path: &Path, base: &Path) -> String {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    let mut parts = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::RootDir => parts.push(String::new()),
+            other => parts.push(other.as_os_str().to_string_lossy().to_string()),
+        }
+    }
+    parts.join("/")
+}
+
+// Names containing a newline or backslash are escaped and the line is prefixed with `\`, as coreutils does
+fn format_manifest_line(hash: &str, path: &str) -> String {
+    if path.contains(['\\', '\n', '\r']) {
+        let escaped = path.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+        format!("\\{}  {}", hash, escaped)
+    } else {
+        format!("{}  {}", hash, path)
+    }
+}
+
+fn parse_manifest_line(line: &str) -> Option<(String, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (hash, path) = line.split_once(' ')?;
+    // A second space marks text mode, `*` binary mode; both hash the same bytes
+    let path = path.strip_prefix([' ', '*'])?;
+    if hash.is_empty() || path.is_empty() {
+        return None;
+    }
+
+    let path = if escaped {
+        let mut unescaped = String::with_capacity(path.len());
+        let mut chars = path.chars();
+        while let Some(char) = chars.next() {
+            if char != '\\' {
+                unescaped.push(char);
+                continue;
+            }
+            match chars.next()? {
+                '\\' => unescaped.push('\\'),
+                'n' => unescaped.push('\n'),
+                'r' => unescaped.push('\r'),
+                _ => return None,
+            }
+        }
+        unescaped
+    } else {
+        path.to_string()
+    };
+    Some((hash.to_ascii_lowercase(), path))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestWriteReport {
+    pub manifest_path: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub entries: usize,
+    pub errors: Vec<ScanError>,
+}
+
+// Directories in `paths` are walked with the scan options, files are listed as given
+pub fn write_manifest(
+  manifest_path: String,
+  paths: Vec<String>,
+  options: ScanOptions,
+  algorithm: Option<HashAlgorithm>,
+) -> Result<ManifestWriteReport, AppError> {
+    let manifest = PathBuf::from(&manifest_path);
+    let algorithm = manifest_algorithm(&manifest, algorithm)?;
+    let mut errors = Vec::new();
+
+    let mut files = Vec::new();
+    for path in paths.iter().map(PathBuf::from) {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        for entry in StorehouseWalker::new(&path, &options)? {
+            match entry {
+                Ok(entry) => files.push(entry.into_path()),
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+    // The manifest never lists itself, e.g. when it is rewritten inside the hashed directory
+    files.retain(|file| *file != manifest);
+    files.sort();
+    files.dedup();
+
+    let hashes: Vec<(PathBuf, io::Result<String>)> = files
+        .into_par_iter()
+        .map(|file| {
+            let hash = hash_file_contents(&file, algorithm);
+            (file, hash)
+        })
+        .collect();
+
+    let base = manifest_dir(&manifest);
+    // Appended rather than replacing the extension, which could name a file of the user
+    let mut temp_name = manifest
+        .file_name()
+        .ok_or_else(|| AppError::invalid_path(&manifest, "Manifest path does not name a file"))?
+        .to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = manifest.with_file_name(temp_name);
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    let mut entries = 0;
+    for (file, hash) in hashes {
+        match hash {
+            Ok(hash) => {
+                writeln!(writer, "{}", format_manifest_line(&hash, &manifest_path_of(&file, base)))?;
+                entries += 1;
+            }
+            Err(e) => errors.push(ScanError::from_io(&file, &e)),
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp_path, &manifest)?;
+
+    Ok(ManifestWriteReport {
+        manifest_path: manifest,
+        algorithm,
+        entries,
+        errors,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ManifestLineStatus {
+    Ok,
+    Failed,
+    Missing,
+    Unreadable,
+    Malformed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestLineResult {
+    // 1-based, as editors count lines
+    pub line: usize,
+    pub path: Option<PathBuf>,
+    pub status: ManifestLineStatus,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestVerifyReport {
+    pub algorithm: HashAlgorithm,
+    pub lines: Vec<ManifestLineResult>,
+}
+
+fn verify_manifest_line(line: usize, content: &str, base: &Path, algorithm: HashAlgorithm) -> ManifestLineResult {
+    let Some((expected, path)) = parse_manifest_line(content) else {
+        return ManifestLineResult {
+            line,
+            path: None,
+            status: ManifestLineStatus::Malformed,
+            message: Some("Expected `<hash>  <path>`".to_string()),
+        };
+    };
+    let path = base.join(path);
+
+    let (status, message) = if expected.len() != algorithm.hex_len() {
+        (ManifestLineStatus::Malformed, Some(format!("Not a {:?} hash", algorithm)))
+    } else {
+        match hash_file_contents(&path, algorithm) {
+            Ok(actual) if actual == expected => (ManifestLineStatus::Ok, None),
+            Ok(actual) => (ManifestLineStatus::Failed, Some(format!("Expected {}, got {}", expected, actual))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (ManifestLineStatus::Missing, None),
+            Err(e) => (ManifestLineStatus::Unreadable, Some(e.to_string())),
+        }
+    };
+    ManifestLineResult {
+        line,
+        path: Some(path),
+        status,
+        message,
+    }
+}
+
+pub fn verify_manifest(manifest_path: String, algorithm: Option<HashAlgorithm>) -> Result<ManifestVerifyReport, AppError> {
+    let manifest = PathBuf::from(&manifest_path);
+    let algorithm = manifest_algorithm(&manifest, algorithm)?;
+    let contents = fs::read_to_string(&manifest).map_err(|e| AppError::io(&manifest, e))?;
+
+    let base = manifest_dir(&manifest);
+    let lines: Vec<(usize, &str)> = contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let lines = lines
+        .into_par_iter()
+        .map(|(line, content)| verify_manifest_line(line, content, base, algorithm))
+        .collect();
+
+    Ok(ManifestVerifyReport { algorithm, lines })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const BLAKE3: &str = "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+
+    fn parsed(hash: &str, path: &str) -> Option<(String, String)> {
+        Some((hash.to_string(), path.to_string()))
+    }
+
+    #[test]
+    fn parses_sha256sum_text_and_binary_mode() {
+        assert_eq!(parse_manifest_line(&format!("{}  photos/a b.jpg", SHA256)), parsed(SHA256, "photos/a b.jpg"));
+        assert_eq!(parse_manifest_line(&format!("{} *photos/a.jpg", SHA256)), parsed(SHA256, "photos/a.jpg"));
+        assert_eq!(parse_manifest_line(&format!("{}  photos/a.jpg", SHA256.to_uppercase())), parsed(SHA256, "photos/a.jpg"));
+    }
+
+    #[test]
+    fn parses_b3sum_lines() {
+        assert_eq!(parse_manifest_line(&format!("{}  video.mp4", BLAKE3)), parsed(BLAKE3, "video.mp4"));
+    }
+
+    #[test]
+    fn parses_escaped_names_written_by_format_manifest_line() {
+        let path = "odd\\name\nwith\rbreaks.png";
+        let line = format_manifest_line(SHA256, path);
+        assert!(line.starts_with('\\'));
+        assert!(!line.contains('\n'));
+        assert_eq!(parse_manifest_line(&line), parsed(SHA256, path));
+    }
+
+    #[test]
+    fn writing_a_manifest_leaves_files_named_like_its_stem_alone() {
+        let dir = std::env::temp_dir().join(format!("tag-anywhere-manifest-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("photos.tmp"), "a file of the user").unwrap();
+
+        let manifest = dir.join("photos.sha256");
+        let report = write_manifest(
+            manifest.to_string_lossy().to_string(),
+            vec![dir.to_string_lossy().to_string()],
+            ScanOptions::default(),
+            None,
+        );
+        let user_file = fs::read_to_string(dir.join("photos.tmp"));
+        let manifest_contents = fs::read_to_string(&manifest);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(report.unwrap().entries, 1);
+        assert_eq!(user_file.unwrap(), "a file of the user");
+        assert!(manifest_contents.unwrap().ends_with("  photos.tmp\n"));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "",
+            SHA256,
+            &format!("{} a.jpg", SHA256),
+            &format!("{}  ", SHA256),
+            "  a.jpg",
+            &format!("\\{}  a\\tb.jpg", SHA256),
+            &format!("\\{}  a\\", SHA256),
+        ] {
+            assert_eq!(parse_manifest_line(line), None, "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::error::AppError;
+use crate::file_thumbnail::{ThumbnailProfile, PREVIEW_SUFFIX, STORYBOARD_SUFFIX};
+
+fn generate_unique_filename(destination: &Path, file_name: &str) -> PathBuf {
+    let mut unique_file_name = file_name.to_string();
+    let mut counter = 1;
+    while destination.join(&unique_file_name).exists() {
+        unique_file_name = format!("{}_{}", counter, file_name);
+        counter += 1;
+    }
+    destination.join(unique_file_name)
+}
+
+pub fn copy_file_to_directory(file_path: String, directory_path: String) -> Result<String, AppError> {
+    let source = Path::new(&file_path);
+    let file_name = source
+        .file_name()
+        .ok_or_else(|| AppError::invalid_path(source, "Invalid file path"))?
+        .to_str()
+        .ok_or_else(|| AppError::invalid_path(source, "Invalid file name"))?;
+    let destination = Path::new(&directory_path);
+
+    let unique_destination = generate_unique_filename(destination, file_name);
+
+    fs::copy(source, &unique_destination).map_err(|e| AppError::io(source, e))?;
+    Ok(unique_destination
+        .to_str()
+        .ok_or_else(|| AppError::invalid_path(&unique_destination, "Failed to convert path to string"))?
+        .to_string())
+}
+
+// Resolves the parent directory so relative, absolute and `..` spellings of a path compare equal.
+// The file name is kept as is, a listed symlink must not keep its target instead.
+fn normalize_path(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
+// Files written next to a cover as `<cover>_<suffix>.<extension>`, they are kept with it
+fn companion_suffixes() -> Vec<&'static str> {
+    let mut suffixes: Vec<&'static str> = ThumbnailProfile::ALL.iter().map(ThumbnailProfile::name).collect();
+    suffixes.extend([STORYBOARD_SUFFIX, PREVIEW_SUFFIX]);
+    suffixes
+}
+
+fn is_companion(path: &Path, listed_stems: &HashSet<String>) -> bool {
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return false;
+    };
+    companion_suffixes().iter().any(|suffix| {
+        stem.strip_suffix(suffix)
+            .and_then(|cover| cover.strip_suffix('_'))
+            .is_some_and(|cover| listed_stems.contains(cover))
+    })
+}
+
+// Returns the deleted files, or with `dry_run` the files that would be deleted
+pub fn delete_unlisted_files(input_paths: Vec<String>, folder_path: String, dry_run: bool) -> Result<Vec<PathBuf>, AppError> {
+    // An empty list is far more likely a failed query than a request to empty the folder
+    if input_paths.is_empty() {
+        return Err(AppError::invalid_input("Refusing to delete every file in the folder, no paths to keep were given"));
+    }
+
+    // Ensure the folder path is a directory
+    let folder = Path::new(&folder_path);
+    if !folder.is_dir() {
+        return Err(AppError::invalid_path(folder, "Provided folder path is not a directory"));
+    }
+    let folder = fs::canonicalize(folder).map_err(|e| AppError::io(folder, e))?;
+
+    // Convert input paths to a HashSet for efficient lookup
+    let input_paths_set: HashSet<PathBuf> = input_paths.iter().map(|path| normalize_path(Path::new(path))).collect();
+    let listed_stems: HashSet<String> = input_paths_set
+        .iter()
+        .filter_map(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .collect();
+
+    // Read the directory
+    let entries = fs::read_dir(&folder).map_err(|e| AppError::io(&folder, e))?;
+
+    // Iterate over the files in the directory
+    let mut deleted = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+
+                // Check if the file is not in the input paths, folders such as the thumbnail cache are kept
+                if path.is_file() && !input_paths_set.contains(&path) && !is_companion(&path, &listed_stems) {
+                    // Delete the file
+                    if !dry_run {
+                        fs::remove_file(&path).map_err(|e| AppError::io(&path, e))?;
+                    }
+                    deleted.push(path);
+                }
+            }
+            Err(e) => return Err(AppError::io(&folder, e)),
+        }
+    }
+
+    Ok(deleted)
+}
+
@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_hash::{hash_file_contents, parse_stored_hash, sample_hash_file_contents, HashAlgorithm};
+use crate::file_scan::{ScanError, ScanOptions, StorehouseWalker};
+use crate::scan_state::{ScanState, ScanStateEntry, SAMPLE_HASH_ALGORITHM};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MissingFile {
+    pub path: String,
+    // `Algorithm:hex` as stored in the index, or bare SHA-256 hex
+    pub expected_hash: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelinkProposal {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    // Further identical copies, e.g. when a file was duplicated while being moved
+    pub other_matches: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RelinkReport {
+    pub proposals: Vec<RelinkProposal>,
+    pub unresolved: Vec<PathBuf>,
+    pub errors: Vec<ScanError>,
+}
+
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+}
+
+// Hashes every candidate once, in parallel, and keeps the successful results by candidate index
+fn hash_candidates<F>(candidates: &[Candidate], indices: HashSet<usize>, errors: &mut Vec<ScanError>, hash: F) -> HashMap<usize, String>
+where
+    F: Fn(&Path) -> io::Result<String> + Sync,
+{
+    let hashes: Vec<(usize, io::Result<String>)> = indices
+        .into_par_iter()
+        .map(|index| (index, hash(&candidates[index].path)))
+        .collect();
+
+    let mut results = HashMap::with_capacity(hashes.len());
+    for (index, hash) in hashes {
+        match hash {
+            Ok(hash) => {
+                results.insert(index, hash);
+            }
+            Err(e) => errors.push(ScanError::from_io(&candidates[index].path, &e)),
+        }
+    }
+    results
+}
+
+// Candidates are narrowed down by the size and sampled hash remembered in the scan cache,
+// including files a scan already reported as removed, so only a handful of files is read
+// in full. Files the cache never knew are compared against the full hash of every candidate,
+// which is slow on large trees.
+pub fn relink_missing_files(
+  missing: Vec<MissingFile>,
+  search_root: String,
+  index_dir: Option<String>,
+  skip_paths: Vec<String>,
+  options: ScanOptions,
+) -> Result<RelinkReport, AppError> {
+    let mut report = RelinkReport::default();
+    let expected: Vec<(PathBuf, HashAlgorithm, String)> = missing
+        .iter()
+        .map(|file| {
+            let (algorithm, hash) = parse_stored_hash(&file.expected_hash)?;
+            Ok((PathBuf::from(&file.path), algorithm, hash))
+        })
+        .collect::<Result<_, AppError>>()?;
+
+    let cached: HashMap<PathBuf, ScanStateEntry> = index_dir
+        .and_then(|index_dir| ScanState::read(Path::new(&index_dir)))
+        .map(|state| state.removed.into_iter().chain(state.entries).collect())
+        .unwrap_or_default();
+
+    let skip_set: HashSet<PathBuf> = skip_paths.into_iter().map(PathBuf::from).collect();
+    let mut candidates = Vec::new();
+    for entry in StorehouseWalker::new(Path::new(&search_root), &options)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+        if skip_set.contains(entry.path()) {
+            continue;
+        }
+        match entry.metadata() {
+            Ok(metadata) => candidates.push(Candidate {
+                path: entry.into_path(),
+                size: metadata.len(),
+            }),
+            Err(e) => report.errors.push(ScanError::from_walk(&e, entry.path())),
+        }
+    }
+
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        by_size.entry(candidate.size).or_default().push(index);
+    }
+
+    // 1. Size, when the cache remembers it
+    let mut matches: Vec<Vec<usize>> = expected
+        .iter()
+        .map(|(path, _, _)| match cached.get(path) {
+            Some(entry) => by_size.get(&entry.size).cloned().unwrap_or_default(),
+            None => (0..candidates.len()).collect(),
+        })
+        .collect();
+
+    // 2. Sampled hash, when the cache remembers it
+    let sampled_indices = expected
+        .iter()
+        .zip(&matches)
+        .filter(|((path, _, _), _)| cached.get(path).is_some_and(|entry| entry.sample_hash.is_some()))
+        .flat_map(|(_, indices)| indices.iter().copied())
+        .collect();
+    let sampled = hash_candidates(&candidates, sampled_indices, &mut report.errors, |path| {
+        sample_hash_file_contents(path, SAMPLE_HASH_ALGORITHM)
+    });
+    for ((path, _, _), indices) in expected.iter().zip(&mut matches) {
+        if let Some(sample_hash) = cached.get(path).and_then(|entry| entry.sample_hash.as_ref()) {
+            indices.retain(|index| sampled.get(index) == Some(sample_hash));
+        }
+    }
+
+    // 3. Full hash to confirm, computed once per candidate and algorithm
+    let mut full: HashMap<HashAlgorithm, HashMap<usize, String>> = HashMap::new();
+    let algorithms: HashSet<HashAlgorithm> = expected.iter().map(|(_, algorithm, _)| *algorithm).collect();
+    for algorithm in algorithms {
+        let indices = expected
+            .iter()
+            .zip(&matches)
+            .filter(|((_, expected_algorithm, _), _)| *expected_algorithm == algorithm)
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .collect();
+        let hashes = hash_candidates(&candidates, indices, &mut report.errors, |path| hash_file_contents(path, algorithm));
+        full.insert(algorithm, hashes);
+    }
+
+    let mut claimed: HashSet<usize> = HashSet::new();
+    for ((path, algorithm, hash), indices) in expected.into_iter().zip(matches) {
+        let mut confirmed: Vec<usize> = indices
+            .into_iter()
+            .filter(|index| full[&algorithm].get(index) == Some(&hash))
+            .collect();
+        confirmed.sort_by(|a, b| candidates[*a].path.cmp(&candidates[*b].path));
+
+        // Identical files that went missing together each get their own copy
+        match confirmed.iter().position(|index| !claimed.contains(index)) {
+            Some(position) => {
+                let to = confirmed.remove(position);
+                claimed.insert(to);
+                report.proposals.push(RelinkProposal {
+                    from: path,
+                    to: candidates[to].path.clone(),
+                    other_matches: confirmed.into_iter().map(|index| candidates[index].path.clone()).collect(),
+                });
+            }
+            None => report.unresolved.push(path),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::scan_state::{commit_scan_state, scan_for_changes};
+
+    // A storehouse and an index directory that are removed again when the test ends
+    struct Storehouse {
+        root: PathBuf,
+    }
+
+    impl Storehouse {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("tag-anywhere-relink-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("files/old")).unwrap();
+            fs::create_dir_all(root.join("files/new")).unwrap();
+            Storehouse { root }
+        }
+
+        fn dir(&self) -> String {
+            self.root.join("files").to_string_lossy().to_string()
+        }
+
+        fn index_dir(&self) -> String {
+            self.root.join("index").to_string_lossy().to_string()
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.root.join("files").join(name)
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            fs::write(self.path(name), contents).unwrap();
+        }
+
+        fn scan(&self) {
+            scan_for_changes(&self.dir(), &self.index_dir(), &ScanOptions::default()).unwrap();
+            commit_scan_state(&self.dir(), &self.index_dir()).unwrap();
+        }
+
+        fn missing(&self, name: &str) -> MissingFile {
+            MissingFile {
+                path: self.path(name).to_string_lossy().to_string(),
+                expected_hash: format!("Sha256:{}", hash_file_contents(&self.path(name), HashAlgorithm::Sha256).unwrap()),
+            }
+        }
+
+        fn relink(&self, missing: Vec<MissingFile>, index_dir: Option<String>) -> RelinkReport {
+            let report = relink_missing_files(missing, self.dir(), index_dir, Vec::new(), ScanOptions::default()).unwrap();
+            assert!(report.errors.is_empty(), "{:?}", report.errors);
+            report
+        }
+    }
+
+    impl Drop for Storehouse {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn proposes_moved_files_with_their_other_copies() {
+        let storehouse = Storehouse::new("moved");
+        storehouse.write("old/a.jpg", "image a");
+        storehouse.write("old/b.jpg", "image b");
+        let missing = storehouse.missing("old/a.jpg");
+        storehouse.scan();
+
+        fs::rename(storehouse.path("old/a.jpg"), storehouse.path("new/a.jpg")).unwrap();
+        fs::copy(storehouse.path("new/a.jpg"), storehouse.path("new/a copy.jpg")).unwrap();
+        storehouse.scan();
+
+        // With and without the candidates narrowed down by the scan cache
+        for index_dir in [None, Some(storehouse.index_dir())] {
+            let report = storehouse.relink(vec![missing.clone()], index_dir);
+            assert!(report.unresolved.is_empty());
+            assert_eq!(report.proposals.len(), 1);
+            let proposal = &report.proposals[0];
+            assert_eq!(proposal.from, storehouse.path("old/a.jpg"));
+            assert_eq!(proposal.to, storehouse.path("new/a copy.jpg"));
+            assert_eq!(proposal.other_matches, vec![storehouse.path("new/a.jpg")]);
+        }
+    }
+
+    #[test]
+    fn modified_files_stay_unresolved() {
+        let storehouse = Storehouse::new("modified");
+        storehouse.write("old/a.jpg", "image a");
+        storehouse.write("old/b.jpg", "image b");
+        let missing = vec![storehouse.missing("old/a.jpg"), storehouse.missing("old/b.jpg")];
+        storehouse.scan();
+
+        // One keeps its size, so only the hashes tell it apart
+        fs::remove_file(storehouse.path("old/a.jpg")).unwrap();
+        storehouse.write("new/a.jpg", "image A");
+        fs::remove_file(storehouse.path("old/b.jpg")).unwrap();
+        storehouse.write("new/b.jpg", "image b, edited");
+        storehouse.scan();
+
+        for index_dir in [None, Some(storehouse.index_dir())] {
+            let report = storehouse.relink(missing.clone(), index_dir);
+            assert!(report.proposals.is_empty());
+            assert_eq!(report.unresolved, vec![storehouse.path("old/a.jpg"), storehouse.path("old/b.jpg")]);
+        }
+    }
+}
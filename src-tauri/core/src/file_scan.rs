@@ -0,0 +1,460 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{DirEntry, Match, Walk, WalkBuilder};
+use mime_guess::from_path;
+use mime_guess::mime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::error::AppError;
+
+pub const TAG_IGNORE_FILE_NAME: &str = ".tagignore";
+
+// Containers that hold either audio or video and cannot be told apart by their header alone
+const AMBIGUOUS_CONTAINERS: [&str; 9] = [
+    "video/mp4",
+    "audio/m4a",
+    "audio/x-m4a",
+    "video/webm",
+    "audio/webm",
+    "video/ogg",
+    "audio/ogg",
+    "video/x-matroska",
+    "audio/x-matroska",
+];
+
+// Globs follow gitignore syntax and are matched relative to the scanned directory.
+// Passing `exclude_globs` replaces the defaults rather than extending them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub max_depth: Option<usize>,
+    pub include_hidden: bool,
+    pub follow_symlinks: bool,
+    pub min_file_size: u64,
+    pub include_globs: Vec<String>,
+    pub exclude_globs: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            max_depth: None,
+            include_hidden: false,
+            follow_symlinks: false,
+            min_file_size: 0,
+            include_globs: Vec::new(),
+            exclude_globs: vec![
+                "@eaDir".to_string(),
+                ".thumbnails".to_string(),
+                "*.part".to_string(),
+                "*.crdownload".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    NotFound,
+    SymlinkLoop,
+    InvalidPath,
+    IgnoreRule,
+    Decode,
+    Io,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn new(path: &Path, kind: ScanErrorKind, message: &str) -> Self {
+        ScanError {
+            path: path.to_path_buf(),
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn from_io(path: &Path, error: &io::Error) -> Self {
+        ScanError::new(path, io_error_kind(error), &error.to_string())
+    }
+
+    pub fn from_walk(error: &ignore::Error, root: &Path) -> Self {
+        let path = walk_error_path(error).unwrap_or(root);
+        ScanError::new(path, walk_error_kind(error), &error.to_string())
+    }
+
+    // For files that were reached but failed to process. Anything that is not an io error
+    // is reported as a decode error.
+    pub fn from_app_error(path: &Path, error: &AppError) -> Self {
+        let kind = match error {
+            AppError::NotFound { .. } => ScanErrorKind::NotFound,
+            AppError::PermissionDenied { .. } => ScanErrorKind::PermissionDenied,
+            AppError::InvalidPath { .. } => ScanErrorKind::InvalidPath,
+            AppError::Io { .. } => ScanErrorKind::Io,
+            _ => ScanErrorKind::Decode,
+        };
+        ScanError::new(path, kind, &error.message())
+    }
+}
+
+fn io_error_kind(error: &io::Error) -> ScanErrorKind {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+        io::ErrorKind::NotFound => ScanErrorKind::NotFound,
+        _ => ScanErrorKind::Io,
+    }
+}
+
+fn walk_error_path(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => walk_error_path(err),
+        ignore::Error::Partial(errors) => errors.iter().find_map(walk_error_path),
+        _ => None,
+    }
+}
+
+fn walk_error_kind(error: &ignore::Error) -> ScanErrorKind {
+    match error {
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => walk_error_kind(err),
+        ignore::Error::Partial(errors) => errors.first().map_or(ScanErrorKind::IgnoreRule, walk_error_kind),
+        ignore::Error::Loop { .. } => ScanErrorKind::SymlinkLoop,
+        ignore::Error::Io(e) => io_error_kind(e),
+        _ => ScanErrorKind::IgnoreRule,
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ScanReport {
+    pub files: Vec<FileInfo>,
+    pub errors: Vec<ScanError>,
+    // Files that were reached but not indexed, e.g. unsupported types or filtered out by size
+    pub skipped: usize,
+}
+
+// Yields the regular files under a directory that pass the scan options and `.tagignore` rules
+pub struct StorehouseWalker {
+    walk: Walk,
+    root: PathBuf,
+    include: Option<GlobSet>,
+    min_file_size: u64,
+    skipped: usize,
+}
+
+fn exclude_overrides(dir: &Path, options: &ScanOptions) -> Result<Override, AppError> {
+    let mut overrides = OverrideBuilder::new(dir);
+    for glob in &options.exclude_globs {
+        overrides
+            .add(&format!("!{}", glob))
+            .map_err(|e| AppError::invalid_input(e.to_string()))?;
+    }
+    overrides.build().map_err(|e| AppError::invalid_input(e.to_string()))
+}
+
+fn include_globs(options: &ScanOptions) -> Result<Option<GlobSet>, AppError> {
+    if options.include_globs.is_empty() {
+        return Ok(None);
+    }
+    let mut include = GlobSetBuilder::new();
+    for glob in &options.include_globs {
+        include.add(Glob::new(glob).map_err(|e| AppError::invalid_input(e.to_string()))?);
+    }
+    Ok(Some(include.build().map_err(|e| AppError::invalid_input(e.to_string()))?))
+}
+
+impl StorehouseWalker {
+    pub fn new(dir: &Path, options: &ScanOptions) -> Result<Self, AppError> {
+        // Excludes go through the walker so whole directories are pruned. Includes are
+        // matched separately because whitelisted overrides would win over `.tagignore`.
+        let walk = WalkBuilder::new(dir)
+            .standard_filters(false)
+            .hidden(!options.include_hidden)
+            .follow_links(options.follow_symlinks)
+            .max_depth(options.max_depth)
+            .add_custom_ignore_filename(TAG_IGNORE_FILE_NAME)
+            .overrides(exclude_overrides(dir, options)?)
+            .build();
+
+        Ok(StorehouseWalker {
+            walk,
+            root: dir.to_path_buf(),
+            include: include_globs(options)?,
+            min_file_size: options.min_file_size,
+            skipped: 0,
+        })
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+impl Iterator for StorehouseWalker {
+    type Item = Result<DirEntry, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.walk.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(ScanError::from_walk(&e, &self.root))),
+            };
+            if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+                continue;
+            }
+
+            if let Some(include) = &self.include {
+                let relative = entry.path().strip_prefix(&self.root).unwrap_or(entry.path());
+                if !include.is_match(relative) {
+                    self.skipped += 1;
+                    continue;
+                }
+            }
+
+            if self.min_file_size > 0 {
+                match entry.metadata() {
+                    Ok(metadata) if metadata.len() < self.min_file_size => {
+                        self.skipped += 1;
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Some(Err(ScanError::from_walk(&e, entry.path()))),
+                }
+            }
+
+            return Some(Ok(entry));
+        }
+    }
+}
+
+// Applies the rules of `StorehouseWalker` to single paths below its directory, e.g. from file
+// system events. `.tagignore` files are read once and kept until `reload` is called.
+pub struct StorehouseFilter {
+    root: PathBuf,
+    options: ScanOptions,
+    exclude: Override,
+    include: Option<GlobSet>,
+    tag_ignores: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl StorehouseFilter {
+    pub fn new(dir: &Path, options: &ScanOptions) -> Result<Self, AppError> {
+        Ok(StorehouseFilter {
+            root: dir.to_path_buf(),
+            options: options.clone(),
+            exclude: exclude_overrides(dir, options)?,
+            include: include_globs(options)?,
+            tag_ignores: HashMap::new(),
+        })
+    }
+
+    pub fn reload(&mut self) {
+        self.tag_ignores.clear();
+    }
+
+    // Whether the walker would yield the file at `path`. Paths that no longer exist are
+    // matched by their name alone.
+    pub fn is_included(&mut self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let depth = relative.components().count();
+        if depth == 0 || self.options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return false;
+        }
+        if !self.options.include_hidden && relative.iter().any(|name| name.to_string_lossy().starts_with('.')) {
+            return false;
+        }
+
+        // The walker prunes excluded directories with everything in them
+        let mut current = self.root.clone();
+        for (index, name) in relative.iter().enumerate() {
+            current.push(name);
+            let is_dir = index + 1 < depth;
+            if self.exclude.matched(&current, is_dir).is_ignore() || self.is_tag_ignored(&current, is_dir) {
+                return false;
+            }
+        }
+
+        if let Some(include) = &self.include {
+            if !include.is_match(relative) {
+                return false;
+            }
+        }
+
+        match fs::symlink_metadata(path) {
+            Err(_) => true,
+            Ok(metadata) if metadata.file_type().is_symlink() && !self.options.follow_symlinks => false,
+            Ok(_) => fs::metadata(path)
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() >= self.options.min_file_size),
+        }
+    }
+
+    // The files the walker would yield below `dir`, e.g. a directory moved into the storehouse
+    pub fn files_in(&mut self, dir: &Path) -> Vec<PathBuf> {
+        let paths: Vec<PathBuf> = WalkBuilder::new(dir)
+            .standard_filters(false)
+            .hidden(!self.options.include_hidden)
+            .follow_links(self.options.follow_symlinks)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+            .map(DirEntry::into_path)
+            .collect();
+        paths.into_iter().filter(|path| self.is_included(path)).collect()
+    }
+
+    // Like the walker, the `.tagignore` closest to the path decides
+    fn is_tag_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        for dir in path.ancestors().skip(1) {
+            let tag_ignore = self.tag_ignores.entry(dir.to_path_buf()).or_insert_with(|| {
+                let file = dir.join(TAG_IGNORE_FILE_NAME);
+                file.is_file().then(|| Gitignore::new(&file).0)
+            });
+            match tag_ignore.as_ref().map(|tag_ignore| tag_ignore.matched(path, is_dir)) {
+                Some(Match::Ignore(_)) => return true,
+                Some(Match::Whitelist(_)) => return false,
+                _ => {}
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FileType {
+    Image,
+    Video,
+    Audio,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub file_type: FileType,
+    pub sniffed_mime: Option<String>,
+    pub extension_mime: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DetectedMime {
+    pub sniffed: Option<mime::Mime>,
+    pub extension: Option<mime::Mime>,
+}
+
+impl DetectedMime {
+    pub fn mime_type(&self) -> mime::Mime {
+        match (&self.sniffed, &self.extension) {
+            (Some(sniffed), Some(extension))
+                if AMBIGUOUS_CONTAINERS.contains(&sniffed.essence_str())
+                    && AMBIGUOUS_CONTAINERS.contains(&extension.essence_str()) =>
+            {
+                extension.clone()
+            }
+            (Some(sniffed), _) => sniffed.clone(),
+            (None, Some(extension)) => extension.clone(),
+            (None, None) => mime::APPLICATION_OCTET_STREAM,
+        }
+    }
+}
+
+// Magic bytes decide the type; the extension is only used when the content is not recognized.
+// A file that cannot be read is an error rather than typed by its name alone.
+pub fn detect_mime(path: &Path) -> io::Result<DetectedMime> {
+    let sniffed = infer::get_from_path(path)?.and_then(|kind| kind.mime_type().parse::<mime::Mime>().ok());
+    let extension = from_path(path).first();
+    Ok(DetectedMime { sniffed, extension })
+}
+
+fn categorize_mime_type(mime_type: &mime::Mime) -> Option<FileType> {
+    match (mime_type.type_(), mime_type.subtype()) {
+        (mime::IMAGE, _) => Some(FileType::Image),
+        (mime::VIDEO, _) => Some(FileType::Video),
+        (mime::AUDIO, _) => Some(FileType::Audio),
+        _ => None,
+    }
+}
+
+pub fn file_type_of(path: &Path) -> io::Result<Option<FileType>> {
+    Ok(categorize_mime_type(&detect_mime(path)?.mime_type()))
+}
+
+// For paths whose contents cannot be read any more, e.g. removed files
+pub fn file_type_by_extension(path: &Path) -> Option<FileType> {
+    from_path(path).first().as_ref().and_then(categorize_mime_type)
+}
+
+// None for files that are not media
+pub fn file_info_of(path: &Path) -> io::Result<Option<FileInfo>> {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Ok(None),
+    };
+    let detected = detect_mime(path)?;
+    Ok(categorize_mime_type(&detected.mime_type()).map(|file_type| FileInfo {
+        path: path.to_path_buf(),
+        name,
+        file_type,
+        sniffed_mime: detected.sniffed.map(|mime_type| mime_type.to_string()),
+        extension_mime: detected.extension.map(|mime_type| mime_type.to_string()),
+    }))
+}
+
+pub fn get_files_with_types(dir: &str, options: &ScanOptions) -> Result<ScanReport, AppError> {
+    let mut report = ScanReport::default();
+    let mut walker = StorehouseWalker::new(Path::new(dir), options)?;
+
+    for entry in &mut walker {
+        match entry {
+            Ok(entry) => match file_info_of(entry.path()) {
+                Ok(Some(file_info)) => report.files.push(file_info),
+                Ok(None) => report.skipped += 1,
+                Err(e) => report.errors.push(ScanError::from_io(entry.path(), &e)),
+            },
+            Err(e) => report.errors.push(e),
+        }
+    }
+    report.skipped += walker.skipped();
+
+    Ok(report)
+}
+
+pub fn get_files_with_types_from_paths(paths: Vec<String>) -> Result<ScanReport, AppError> {
+    let mut report = ScanReport::default();
+
+    for path_str in paths {
+        let path = PathBuf::from(path_str);
+        if path.file_name().is_none() {
+            report.errors.push(ScanError::new(&path, ScanErrorKind::InvalidPath, "Path does not name a file"));
+            continue;
+        }
+
+        match path.metadata() {
+            Ok(metadata) if metadata.is_file() => match file_info_of(&path) {
+                Ok(Some(file_info)) => report.files.push(file_info),
+                Ok(None) => report.skipped += 1,
+                Err(e) => report.errors.push(ScanError::from_io(&path, &e)),
+            },
+            Ok(_) => report.skipped += 1,
+            Err(e) => report.errors.push(ScanError::from_io(&path, &e)),
+        }
+    }
+
+    Ok(report)
+}
@@ -1,13 +1,12 @@
 use std::fs;
-use std::process::Command;
-use mime_guess::MimeGuess;
+use std::path::Path;
 use serde::Serialize;
 use std::time::{UNIX_EPOCH, SystemTime};
 use regex::Regex;
-use std::os::windows::process::CommandExt;
-const CREATE_NO_WINDOW: u32 = 0x08000000;
 
 use crate::error::AppError;
+use crate::file_scan::detect_mime;
+use crate::tool_resolver::{tool_command, Tool};
 
 #[derive(Serialize)]
 pub struct FileStats {
@@ -20,9 +19,9 @@ pub struct FileStats {
 }
 
 pub fn get_stats(path: String) -> Result<FileStats, AppError> {
-    let metadata = fs::metadata(&path).map_err(AppError::from)?;
+    let metadata = fs::metadata(&path).map_err(|e| AppError::io(Path::new(&path), e))?;
     let file_size = metadata.len();
-    let mime_type = MimeGuess::from_path(&path).first_or_octet_stream().to_string();
+    let mime_type = detect_mime(Path::new(&path)).map_err(|e| AppError::io(Path::new(&path), e))?.mime_type().to_string();
     let created = metadata
         .created()
         .unwrap_or(SystemTime::now())
@@ -49,7 +48,7 @@ pub fn get_stats(path: String) -> Result<FileStats, AppError> {
         duration = audio_metadata.duration;
         frame_rate = None;
     } else {
-        return Err(AppError::new("Unsupported file type"));
+        return Err(AppError::unsupported_type(Path::new(&path), format!("Unsupported file type {}", mime_type)));
     }
     Ok(FileStats {
         size: file_size,
@@ -62,10 +61,9 @@ pub fn get_stats(path: String) -> Result<FileStats, AppError> {
 }
 
 fn get_image_dimensions(path: &str) -> Option<(u32, u32)> {
-    let imagemagick_path = "bin/magick/magick.exe";
-    let output = Command::new(imagemagick_path)
-        .args(&["identify", "-format", "%wx%h", path])
-        .creation_flags(CREATE_NO_WINDOW)
+    let output = tool_command(Tool::Magick)
+        .ok()?
+        .args([path, "-format", "%wx%h", "info:"])
         .output()
         .ok()?;
     let output_str = String::from_utf8_lossy(&output.stdout);
@@ -87,12 +85,9 @@ struct VideoMetadata {
 }
 
 fn get_video_metadata(path: &str) -> Result<VideoMetadata, AppError> {
-    let ffmpeg_path = "bin/ffmpeg/ffmpeg-win.exe";
-    let output = Command::new(ffmpeg_path)
-        .args(&["-i", path])
-        .creation_flags(CREATE_NO_WINDOW)
-        .output()
-        .map_err(AppError::from)?;
+    let output = tool_command(Tool::Ffmpeg)?
+        .args(["-i", path])
+        .output()?;
     let output_str = String::from_utf8_lossy(&output.stderr);
 
     let duration = parse_duration(&output_str)?;
@@ -107,30 +102,30 @@ fn get_video_metadata(path: &str) -> Result<VideoMetadata, AppError> {
 }
 
 fn parse_duration(output: &str) -> Result<Option<f64>, AppError> {
-    let duration_re = Regex::new(r"Duration: (\d+):(\d+):(\d+\.\d+)").map_err(|e| AppError::new(&e.to_string()))?;
+    let duration_re = Regex::new(r"Duration: (\d+):(\d+):(\d+\.\d+)").map_err(|e| AppError::internal(e.to_string()))?;
     if let Some(caps) = duration_re.captures(output) {
-        let hours: f64 = caps[1].parse::<f64>().map_err(|e| AppError::new(&e.to_string()))?;
-        let minutes = caps[2].parse::<f64>().map_err(|e| AppError::new(&e.to_string()))?;
-        let seconds = caps[3].parse::<f64>().map_err(|e| AppError::new(&e.to_string()))?;
+        let hours: f64 = caps[1].parse::<f64>().map_err(|e| AppError::internal(e.to_string()))?;
+        let minutes = caps[2].parse::<f64>().map_err(|e| AppError::internal(e.to_string()))?;
+        let seconds = caps[3].parse::<f64>().map_err(|e| AppError::internal(e.to_string()))?;
         return Ok(Some(hours * 3600.0 + minutes * 60.0 + seconds));
     }
     Ok(None)
 }
 
 fn parse_frame_rate(output: &str) -> Result<Option<f64>, AppError> {
-    let frame_rate_re = Regex::new(r"(\d+(?:\.\d+)?) fps").map_err(|e| AppError::new(&e.to_string()))?;
+    let frame_rate_re = Regex::new(r"(\d+(?:\.\d+)?) fps").map_err(|e| AppError::internal(e.to_string()))?;
     if let Some(caps) = frame_rate_re.captures(output) {
-        let frame_rate = caps[1].parse::<f64>().map_err(|e| AppError::new(&e.to_string()))?;
+        let frame_rate = caps[1].parse::<f64>().map_err(|e| AppError::internal(e.to_string()))?;
         return Ok(Some(frame_rate));
     }
     Ok(None)
 }
 
 fn parse_dimensions(output: &str) -> Result<Option<(u32, u32)>, AppError> {
-    let dimension_re = Regex::new(r"(\d+)x(\d+) \[SAR").map_err(|e| AppError::new(&e.to_string()))?;
+    let dimension_re = Regex::new(r"(\d+)x(\d+) \[SAR").map_err(|e| AppError::internal(e.to_string()))?;
     if let Some(caps) = dimension_re.captures(output) {
-        let width = caps[1].parse::<u32>().map_err(|e| AppError::new(&e.to_string()))?;
-        let height = caps[2].parse::<u32>().map_err(|e| AppError::new(&e.to_string()))?;
+        let width = caps[1].parse::<u32>().map_err(|e| AppError::internal(e.to_string()))?;
+        let height = caps[2].parse::<u32>().map_err(|e| AppError::internal(e.to_string()))?;
         return Ok(Some((width, height)));
     }
     Ok(None)
@@ -142,12 +137,9 @@ struct AudioMetadata {
 }
 
 fn get_audio_metadata(path: &str) -> Result<AudioMetadata, AppError> {
-    let ffmpeg_path = "bin/ffmpeg/ffmpeg-win.exe";
-    let output = Command::new(ffmpeg_path)
-        .args(&["-i", path])
-        .creation_flags(CREATE_NO_WINDOW)
-        .output()
-        .map_err(AppError::from)?;
+    let output = tool_command(Tool::Ffmpeg)?
+        .args(["-i", path])
+        .output()?;
     let output_str = String::from_utf8_lossy(&output.stderr);
 
     let duration = parse_duration(&output_str)?;
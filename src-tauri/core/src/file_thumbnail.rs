@@ -0,0 +1,845 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    str::FromStr
+};
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    DynamicImage, GenericImage, GrayImage, ImageError, ImageFormat, ImageReader, RgbImage
+};
+use mime_guess::mime;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_scan::detect_mime;
+use crate::perceptual_hash::decode_image;
+use crate::tool_resolver::{tool_command, Tool};
+use crate::video_frame::{extract_frame, extract_gray_frame, get_dimensions, get_duration};
+
+const THUMBNAIL_QUALITY: u8 = 85;
+// 1 is the slowest and smallest, 10 the fastest
+const AVIF_SPEED: u8 = 8;
+const COVER_CANDIDATES: usize = 12;
+const COVER_CANDIDATE_SIZE: u32 = 160;
+// Mean luminance outside this range is a black or blown out frame
+const MIN_COVER_BRIGHTNESS: f64 = 24.0;
+const MAX_COVER_BRIGHTNESS: f64 = 232.0;
+// Luminance standard deviation below this is a flat frame, e.g. a fade or a plain title card
+const MIN_COVER_CONTRAST: f64 = 12.0;
+// Longer videos get a wider interval, which keeps the sprite sheet within a few megabytes
+const MAX_STORYBOARD_TILES: usize = 400;
+const MAX_STORYBOARD_TILE_WIDTH: u32 = 1280;
+// About 100 MB once decoded to RGB
+const MAX_STORYBOARD_PIXELS: u64 = 32 * 1024 * 1024;
+
+pub const PREVIEW_SUFFIX: &str = "preview";
+pub const STORYBOARD_SUFFIX: &str = "storyboard";
+
+// Percentage to scale an image down to, chosen by its smallest side
+fn resize_percentage(width: u32, height: u32) -> Option<u32> {
+    let smallest_side = width.min(height);
+    if smallest_side < 1024 {
+        None
+    } else if smallest_side < 2048 {
+        Some(65)
+    } else {
+        Some(50)
+    }
+}
+
+fn extract_frame_with_ffmpeg(file_path: &str, frame_number: usize, temp_frame_path: &str) -> Result<(), AppError> {
+    tool_command(Tool::Ffmpeg)?
+        .args([
+            "-i", file_path,
+            "-vf", &format!("select=gte(n\\,{})", frame_number),
+            "-vsync", "vfr",
+            "-frames:v", "1",
+            temp_frame_path
+        ])
+        .run()?;
+
+    Ok(())
+}
+
+fn extract_frame_with_ffmpeg_by_time(file_path: &str, time: f64, temp_frame_path: &str) -> Result<(), AppError> {
+    tool_command(Tool::Ffmpeg)?
+        .args([
+            "-ss", &time.to_string(),
+            "-i", file_path,
+            "-vframes", "1",
+            temp_frame_path
+        ])
+        .run()?;
+
+    Ok(())
+}
+
+fn invalid_dimensions(input_path: &str, output: &str) -> AppError {
+    AppError::Decode {
+        path: Some(PathBuf::from(input_path)),
+        message: format!("Invalid dimensions format {:?}", output.trim()),
+    }
+}
+
+fn compress_image_with_imagemagick(
+    input_path: &str,
+    output_path: &str,
+) -> Result<(), AppError> {
+    // Get image dimensions
+    let dimensions_output = tool_command(Tool::Magick)?
+        .args([
+            input_path,
+            "-format", "%wx%h",
+            "info:",
+        ])
+        .run()?;
+
+    let dimensions_str = String::from_utf8_lossy(&dimensions_output.stdout);
+    let dimensions: Vec<&str> = dimensions_str.trim().split('x').collect();
+    if dimensions.len() != 2 {
+        return Err(invalid_dimensions(input_path, &dimensions_str));
+    }
+
+    let width: u32 = dimensions[0].parse::<u32>().map_err(|_| invalid_dimensions(input_path, &dimensions_str))?;
+    let height: u32 = dimensions[1].parse::<u32>().map_err(|_| invalid_dimensions(input_path, &dimensions_str))?;
+
+    // Build ImageMagick command
+    let mut command = tool_command(Tool::Magick)?;
+    command.arg(input_path);
+    if let Some(percentage) = resize_percentage(width, height) {
+        command.args(["-resize", &format!("{}%", percentage)]);
+    }
+    command.args(["-quality", &THUMBNAIL_QUALITY.to_string(), output_path]);
+
+    // Run the command
+    command.run()?;
+
+    Ok(())
+}
+
+// Returns false when the image crate cannot decode the input, so the caller can fall back to ImageMagick
+fn compress_image_natively(input_path: &str, output_path: &str) -> Result<bool, AppError> {
+    let reader = ImageReader::open(input_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| AppError::io(Path::new(input_path), e))?;
+    let image = match reader.decode() {
+        Ok(image) => image,
+        Err(ImageError::Unsupported(_)) | Err(ImageError::Decoding(_)) | Err(ImageError::Limits(_)) => return Ok(false),
+        Err(e) => return Err(AppError::from(e).with_path(Path::new(input_path))),
+    };
+
+    let image = match resize_percentage(image.width(), image.height()) {
+        Some(percentage) => {
+            let width = (image.width() * percentage / 100).max(1);
+            let height = (image.height() * percentage / 100).max(1);
+            image.resize_exact(width, height, FilterType::Triangle)
+        }
+        None => image,
+    };
+
+    let writer = BufWriter::new(File::create(output_path)?);
+    let mut encoder = JpegEncoder::new_with_quality(writer, THUMBNAIL_QUALITY);
+    encoder
+        .encode_image(&image.to_rgb8())
+        .map_err(|e| AppError::from(e).with_path(Path::new(output_path)))?;
+
+    Ok(true)
+}
+
+fn compress_image(input_path: &str, output_path: &str) -> Result<(), AppError> {
+    if compress_image_natively(input_path, output_path)? {
+        return Ok(());
+    }
+    compress_image_with_imagemagick(input_path, output_path)
+}
+
+fn handle_video(file_path: &str, temp_frame_path: &str, frame_number: Option<usize>) -> Result<Option<String>, AppError> {
+    let frame_to_extract = frame_number.unwrap_or(0);
+    extract_frame_with_ffmpeg(file_path, frame_to_extract, temp_frame_path)?;
+    Ok(Some(temp_frame_path.to_string()))
+}
+
+fn handle_video_by_time(file_path: &str, temp_frame_path: &str, time: f64) -> Result<Option<String>, AppError> {
+    extract_frame_with_ffmpeg_by_time(file_path, time, temp_frame_path)?;
+    Ok(Some(temp_frame_path.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CoverSelection {
+    // The given frame, or the first one
+    #[default]
+    Fixed,
+    // The best of frames sampled across the video, falling back to the given frame
+    Smart,
+}
+
+// Weighs sharpness, contrast and exposure to a score between 0 and 1.
+// None for black, blown out and flat frames.
+pub fn score_cover_frame(frame: &GrayImage) -> Option<f64> {
+    let (width, height) = frame.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    let pixels = frame.as_raw();
+    let count = pixels.len() as f64;
+    let brightness = pixels.iter().map(|&pixel| pixel as f64).sum::<f64>() / count;
+    let contrast = standard_deviation(pixels.iter().map(|&pixel| pixel as f64), brightness, count);
+    if !(MIN_COVER_BRIGHTNESS..=MAX_COVER_BRIGHTNESS).contains(&brightness) || contrast < MIN_COVER_CONTRAST {
+        return None;
+    }
+
+    // Spread of the Laplacian, which stays low for out of focus and motion blurred frames
+    let at = |x: u32, y: u32| frame.get_pixel(x, y)[0] as f64;
+    let laplacian: Vec<f64> = (1..height - 1)
+        .flat_map(|y| (1..width - 1).map(move |x| (x, y)))
+        .map(|(x, y)| 4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1))
+        .collect();
+    let laplacian_count = laplacian.len() as f64;
+    let laplacian_mean = laplacian.iter().sum::<f64>() / laplacian_count;
+    let sharpness = standard_deviation(laplacian.into_iter(), laplacian_mean, laplacian_count);
+
+    let exposure = 1.0 - (brightness - 128.0).abs() / 128.0;
+    Some(0.5 * (sharpness / 40.0).min(1.0) + 0.3 * (contrast / 64.0).min(1.0) + 0.2 * exposure)
+}
+
+fn standard_deviation(values: impl Iterator<Item = f64>, mean: f64, count: f64) -> f64 {
+    (values.map(|value| (value - mean).powi(2)).sum::<f64>() / count).sqrt()
+}
+
+// Scores frames spread over the video, away from the first and last one, and returns
+// the time of the best. None when every candidate is black, flat or failed to decode.
+pub fn select_cover_time(file_path: &str) -> Result<Option<f64>, AppError> {
+    let duration = get_duration(file_path)?;
+    let best = (0..COVER_CANDIDATES)
+        .into_par_iter()
+        .filter_map(|index| {
+            let time = duration * (index + 1) as f64 / (COVER_CANDIDATES + 1) as f64;
+            let frame = extract_gray_frame(file_path, time, COVER_CANDIDATE_SIZE, COVER_CANDIDATE_SIZE).ok()?;
+            Some((time, score_cover_frame(&frame)?))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+
+    Ok(best.map(|(time, _)| time))
+}
+
+// An explicit time wins over the smart pick, which wins over the fixed frame
+fn handle_video_cover(
+    file_path: &str,
+    temp_frame_path: &str,
+    frame_number: Option<usize>,
+    time: Option<f64>,
+    cover_selection: CoverSelection,
+) -> Result<Option<String>, AppError> {
+    let time = time.or_else(|| match cover_selection {
+        // Videos FFprobe reports no duration for keep the fixed frame
+        CoverSelection::Smart => select_cover_time(file_path).ok().flatten(),
+        CoverSelection::Fixed => None,
+    });
+    match time {
+        Some(time) => handle_video_by_time(file_path, temp_frame_path, time),
+        None => handle_video(file_path, temp_frame_path, frame_number),
+    }
+}
+
+fn handle_image(file_path: &str, output_path: &str) -> Result<(), AppError> {
+    compress_image(file_path, output_path)
+}
+
+pub fn create_thumbnail(
+    cover_name: String,
+    file_path: String,
+    index_dir: String,
+    frame_number: Option<usize>,
+    time: Option<f64>,
+    cover_selection: CoverSelection
+) -> Result<Option<String>, AppError> {
+    let index_dir = Path::new(&index_dir);
+    let temp_frame_path = index_dir.join(format!("{}.png", cover_name)).to_string_lossy().to_string();
+    let thumbnail_path = index_dir.join(format!("{}.jpg", cover_name)).to_string_lossy().to_string();
+
+    // Delete Existing Cover File
+    if fs::metadata(&thumbnail_path).is_ok() {
+        fs::remove_file(&thumbnail_path).map_err(|e| AppError::io(Path::new(&thumbnail_path), e))?;
+    }
+
+    // Determine MIME Type and Create Thumbnail
+    let mime_type = detect_mime(Path::new(&file_path)).map_err(|e| AppError::io(Path::new(&file_path), e))?.mime_type();
+
+    if mime_type.type_() == mime::VIDEO {
+        handle_video_cover(&file_path, &temp_frame_path, frame_number, time, cover_selection)?;
+        compress_image(&temp_frame_path, &thumbnail_path)?;
+    } else if mime_type.type_() == mime::IMAGE {
+        handle_image(&file_path, &thumbnail_path)?;
+    } else {
+        return Ok(None);
+    }
+
+    if fs::metadata(&temp_frame_path).is_ok() {
+        fs::remove_file(&temp_frame_path).map_err(|e| AppError::io(Path::new(&temp_frame_path), e))?;
+    }
+
+    Ok(Some(thumbnail_path))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PreviewFormat {
+    #[default]
+    WebP,
+    Gif,
+}
+
+impl PreviewFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PreviewFormat::WebP => "webp",
+            PreviewFormat::Gif => "gif",
+        }
+    }
+}
+
+impl FromStr for PreviewFormat {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "webp" => Ok(PreviewFormat::WebP),
+            "gif" => Ok(PreviewFormat::Gif),
+            _ => Err(AppError::invalid_input(format!("Unknown preview format {}", name))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviewOptions {
+    pub segments: usize,
+    // Seconds of each segment
+    pub segment_duration: f64,
+    pub width: u32,
+    pub fps: u32,
+    pub format: PreviewFormat,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        PreviewOptions {
+            segments: 4,
+            segment_duration: 1.5,
+            width: 320,
+            fps: 12,
+            format: PreviewFormat::WebP,
+        }
+    }
+}
+
+// Start times of segments centred at even fractions of the duration, like the cover candidates.
+// Videos shorter than all segments together are used whole.
+fn preview_segments(duration: f64, options: &PreviewOptions) -> Vec<(f64, f64)> {
+    if duration <= options.segment_duration * options.segments as f64 {
+        return vec![(0.0, duration)];
+    }
+    (0..options.segments)
+        .map(|index| {
+            let centre = duration * (index + 1) as f64 / (options.segments + 1) as f64;
+            ((centre - options.segment_duration / 2.0).max(0.0), options.segment_duration)
+        })
+        .collect()
+}
+
+// Writes a silent looping `<cover_name>_preview.<extension>` next to the cover, stitched from
+// short segments spread over the video. Returns None for files that are not videos.
+pub fn create_video_preview(
+    cover_name: String,
+    file_path: String,
+    index_dir: String,
+    options: PreviewOptions,
+) -> Result<Option<String>, AppError> {
+    if detect_mime(Path::new(&file_path)).map_err(|e| AppError::io(Path::new(&file_path), e))?.mime_type().type_() != mime::VIDEO {
+        return Ok(None);
+    }
+    if options.segments == 0 || options.width == 0 || options.fps == 0 || !(options.segment_duration.is_finite() && options.segment_duration > 0.0) {
+        return Err(AppError::invalid_input("Preview segments, segment duration, width and fps must be positive"));
+    }
+
+    let duration = get_duration(&file_path)?;
+    let segments = preview_segments(duration, &options);
+    let preview_path = Path::new(&index_dir)
+        .join(format!("{}_{}.{}", cover_name, PREVIEW_SUFFIX, options.format.extension()))
+        .to_string_lossy()
+        .to_string();
+
+    // Every segment is its own input so FFmpeg seeks to it instead of decoding the video up to it
+    let mut command = tool_command(Tool::Ffmpeg)?;
+    command.args(["-v", "error", "-y"]);
+    for (start, length) in &segments {
+        command.args(["-ss", &start.to_string(), "-t", &length.to_string(), "-i", &file_path]);
+    }
+
+    let mut filter: String = (0..segments.len())
+        .map(|index| format!("[{}:v]fps={},scale={}:-2,setsar=1[v{}];", index, options.fps, options.width, index))
+        .collect();
+    filter.extend((0..segments.len()).map(|index| format!("[v{}]", index)));
+    filter.push_str(&format!("concat=n={}:v=1:a=0", segments.len()));
+    match options.format {
+        // A palette from the clip itself keeps GIF banding down
+        PreviewFormat::Gif => filter.push_str(",split[a][b];[a]palettegen[p];[b][p]paletteuse[out]"),
+        PreviewFormat::WebP => filter.push_str("[out]"),
+    }
+    command.args(["-filter_complex", &filter, "-map", "[out]", "-an", "-loop", "0"]);
+    if options.format == PreviewFormat::WebP {
+        command.args(["-c:v", "libwebp", "-quality", "60"]);
+    }
+    command.arg(&preview_path).run()?;
+
+    Ok(Some(preview_path))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThumbnailWithPreview {
+    pub thumbnail_path: Option<String>,
+    // None for images, or when the preview failed
+    pub preview_path: Option<String>,
+    // Why the preview failed, the cover is still usable
+    pub preview_error: Option<AppError>,
+}
+
+pub fn create_thumbnail_with_preview(
+    cover_name: String,
+    file_path: String,
+    index_dir: String,
+    frame_number: Option<usize>,
+    time: Option<f64>,
+    cover_selection: CoverSelection,
+    preview: PreviewOptions,
+) -> Result<ThumbnailWithPreview, AppError> {
+    let thumbnail_path = create_thumbnail(cover_name.clone(), file_path.clone(), index_dir.clone(), frame_number, time, cover_selection)?;
+    let (preview_path, preview_error) = match create_video_preview(cover_name, file_path, index_dir, preview) {
+        Ok(preview_path) => (preview_path, None),
+        Err(e) => (None, Some(e)),
+    };
+    Ok(ThumbnailWithPreview {
+        thumbnail_path,
+        preview_path,
+        preview_error,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ThumbnailProfile {
+    Grid,
+    Cover,
+    Detail,
+}
+
+impl ThumbnailProfile {
+    pub const ALL: [ThumbnailProfile; 3] = [ThumbnailProfile::Grid, ThumbnailProfile::Cover, ThumbnailProfile::Detail];
+
+    // Longest side, or both sides of the square cover
+    pub fn size(&self) -> u32 {
+        match self {
+            ThumbnailProfile::Grid => 256,
+            ThumbnailProfile::Cover => 512,
+            ThumbnailProfile::Detail => 1024,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThumbnailProfile::Grid => "grid",
+            ThumbnailProfile::Cover => "cover",
+            ThumbnailProfile::Detail => "detail",
+        }
+    }
+
+    // Images are never scaled up, small ones keep their size or get a smaller square
+    pub fn resize(&self, image: &DynamicImage) -> DynamicImage {
+        let size = self.size();
+        match self {
+            ThumbnailProfile::Cover => {
+                let side = size.min(image.width()).min(image.height());
+                image.resize_to_fill(side, side, FilterType::Triangle)
+            }
+            _ if image.width() <= size && image.height() <= size => image.clone(),
+            _ => image.resize(size, size, FilterType::Triangle),
+        }
+    }
+}
+
+impl FromStr for ThumbnailProfile {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        ThumbnailProfile::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| AppError::invalid_input(format!("Unknown thumbnail profile {}", name)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpeg,
+    WebP,
+    Avif,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::WebP => "webp",
+            ThumbnailFormat::Avif => "avif",
+        }
+    }
+
+    // Largest width or height the encoder accepts
+    pub fn max_dimension(&self) -> u32 {
+        match self {
+            ThumbnailFormat::Jpeg | ThumbnailFormat::Avif => 65535,
+            ThumbnailFormat::WebP => 16383,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ThumbnailOptions {
+    // Every profile when empty
+    #[serde(default)]
+    pub profiles: Vec<ThumbnailProfile>,
+    #[serde(default)]
+    pub format: ThumbnailFormat,
+    #[serde(default)]
+    pub cover_selection: CoverSelection,
+}
+
+impl FromStr for ThumbnailFormat {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(ThumbnailFormat::Jpeg),
+            "webp" => Ok(ThumbnailFormat::WebP),
+            "avif" => Ok(ThumbnailFormat::Avif),
+            _ => Err(AppError::invalid_input(format!("Unknown thumbnail format {}", name))),
+        }
+    }
+}
+
+pub type ThumbnailSet = BTreeMap<ThumbnailProfile, String>;
+
+// The image crate only encodes lossless WebP, which is larger than a JPEG of the same thumbnail.
+// The lossy encoding goes through ImageMagick from a temporary PNG instead, and only falls back
+// to the lossless encoder when ImageMagick is not installed.
+fn encode_lossy_webp(image: &DynamicImage, path: &Path) -> Result<(), AppError> {
+    let mut command = match tool_command(Tool::Magick) {
+        Ok(command) => command,
+        Err(AppError::ToolMissing { .. }) => {
+            let writer = BufWriter::new(File::create(path).map_err(|e| AppError::io(path, e))?);
+            return DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_with_encoder(WebPEncoder::new_lossless(writer))
+                .map_err(|e| AppError::from(e).with_path(path));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let temp_path = path.with_extension("webp.png");
+    image
+        .save_with_format(&temp_path, ImageFormat::Png)
+        .map_err(|e| AppError::from(e).with_path(&temp_path))?;
+    let encoded = command
+        .arg(&temp_path)
+        .args(["-quality", &THUMBNAIL_QUALITY.to_string()])
+        .arg(path)
+        .run();
+    let _ = fs::remove_file(&temp_path);
+    encoded.map(|_| ())
+}
+
+pub fn encode_thumbnail(image: &DynamicImage, path: &Path, format: ThumbnailFormat) -> Result<(), AppError> {
+    let writer = || File::create(path).map(BufWriter::new).map_err(|e| AppError::io(path, e));
+    let encoded = match format {
+        ThumbnailFormat::Jpeg => {
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(writer()?, THUMBNAIL_QUALITY))
+        }
+        ThumbnailFormat::WebP => return encode_lossy_webp(image, path),
+        ThumbnailFormat::Avif => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(AvifEncoder::new_with_speed_quality(writer()?, AVIF_SPEED, THUMBNAIL_QUALITY)),
+    };
+    encoded.map_err(|e| AppError::from(e).with_path(path))
+}
+
+// Decodes with the image crate, and converts through ImageMagick what it cannot read, e.g. HEIC or PSD
+fn decode_source_image(file_path: &str, temp_frame_path: &str) -> Result<DynamicImage, AppError> {
+    match decode_image(Path::new(file_path)) {
+        Err(AppError::UnsupportedType { .. }) | Err(AppError::Decode { .. }) => {}
+        decoded => return decoded,
+    }
+
+    tool_command(Tool::Magick)?
+        .args([&format!("{}[0]", file_path), temp_frame_path])
+        .run()?;
+    let image = decode_image(Path::new(temp_frame_path));
+    let _ = fs::remove_file(temp_frame_path);
+    image
+}
+
+// Decodes the source once and writes `<cover_name>_<profile>.<extension>` for every profile.
+// Returns None for files that are neither images nor videos.
+pub fn create_thumbnail_set(
+    cover_name: String,
+    file_path: String,
+    index_dir: String,
+    frame_number: Option<usize>,
+    time: Option<f64>,
+    options: ThumbnailOptions,
+) -> Result<Option<ThumbnailSet>, AppError> {
+    let index_dir = Path::new(&index_dir);
+    let temp_frame_path = index_dir.join(format!("{}.png", cover_name)).to_string_lossy().to_string();
+
+    let mime_type = detect_mime(Path::new(&file_path)).map_err(|e| AppError::io(Path::new(&file_path), e))?.mime_type();
+    let image = if mime_type.type_() == mime::VIDEO {
+        handle_video_cover(&file_path, &temp_frame_path, frame_number, time, options.cover_selection)?;
+        let image = decode_image(Path::new(&temp_frame_path));
+        let _ = fs::remove_file(&temp_frame_path);
+        image?
+    } else if mime_type.type_() == mime::IMAGE {
+        decode_source_image(&file_path, &temp_frame_path)?
+    } else {
+        return Ok(None);
+    };
+
+    let profiles = if options.profiles.is_empty() {
+        ThumbnailProfile::ALL.to_vec()
+    } else {
+        options.profiles
+    };
+    let thumbnails = profiles
+        .into_par_iter()
+        .map(|profile| {
+            let path = index_dir.join(format!("{}_{}.{}", cover_name, profile.name(), options.format.extension()));
+            encode_thumbnail(&profile.resize(&image), &path, options.format)?;
+            Ok((profile, path.to_string_lossy().to_string()))
+        })
+        .collect::<Result<ThumbnailSet, AppError>>()?;
+
+    Ok(Some(thumbnails))
+}
+
+// Frames are taken every `interval` seconds and laid out left to right, top to bottom
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoryboardOptions {
+    pub interval: f64,
+    pub columns: u32,
+    pub tile_width: u32,
+    pub format: ThumbnailFormat,
+}
+
+impl Default for StoryboardOptions {
+    fn default() -> Self {
+        StoryboardOptions {
+            interval: 10.0,
+            columns: 10,
+            tile_width: 160,
+            format: ThumbnailFormat::Jpeg,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Storyboard {
+    pub sprite_path: String,
+    pub vtt_path: String,
+    // Can be wider than requested for long videos
+    pub interval: f64,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tiles: usize,
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+// Fits the sprite sheet within the size limit of its format and the pixel budget, first with
+// fewer columns and then with fewer tiles, which the caller spreads over a wider interval.
+// Returns the tile count and columns.
+fn storyboard_layout(tiles: usize, columns: u32, tile_width: u32, tile_height: u32, format: ThumbnailFormat) -> Result<(usize, u32), AppError> {
+    let max_dimension = format.max_dimension();
+    let tile_pixels = tile_width as u64 * tile_height as u64;
+    if tile_width > max_dimension || tile_height > max_dimension || tile_pixels > MAX_STORYBOARD_PIXELS {
+        return Err(AppError::invalid_input(format!(
+            "Storyboard tiles of {}x{} do not fit a {:?} sprite sheet",
+            tile_width, tile_height, format
+        )));
+    }
+
+    let columns = (columns as u64)
+        .min(tiles as u64)
+        .min((max_dimension / tile_width) as u64)
+        .min(MAX_STORYBOARD_PIXELS / tile_pixels);
+    let rows = ((max_dimension / tile_height) as u64).min(MAX_STORYBOARD_PIXELS / (tile_pixels * columns));
+    let tiles = tiles.min((columns * rows) as usize);
+    Ok((tiles, columns as u32))
+}
+
+// Writes `<name>_storyboard.<extension>` and `<name>_storyboard.vtt`, whose cues point at
+// the sprite sheet with `#xywh=` fragments for hover scrubbing. Returns None for files that are not videos.
+pub fn create_video_storyboard(
+    name: String,
+    file_path: String,
+    index_dir: String,
+    options: StoryboardOptions,
+) -> Result<Option<Storyboard>, AppError> {
+    if detect_mime(Path::new(&file_path)).map_err(|e| AppError::io(Path::new(&file_path), e))?.mime_type().type_() != mime::VIDEO {
+        return Ok(None);
+    }
+    if !options.interval.is_finite() || options.interval <= 0.0 || options.columns == 0 || options.tile_width == 0 {
+        return Err(AppError::invalid_input("Storyboard interval, columns and tile width must be positive"));
+    }
+    if options.tile_width > MAX_STORYBOARD_TILE_WIDTH {
+        return Err(AppError::invalid_input(format!(
+            "Storyboard tile width must be at most {}",
+            MAX_STORYBOARD_TILE_WIDTH
+        )));
+    }
+
+    // Tiles keep the aspect ratio of the video at an even height, as FFmpeg scales them
+    let (video_width, video_height) = get_dimensions(&file_path)?;
+    let tile_height = ((options.tile_width as f64 * video_height as f64 / video_width as f64 / 2.0).round() as u32 * 2).max(2);
+    let duration = get_duration(&file_path)?;
+    let tiles = ((duration / options.interval).ceil() as usize).clamp(1, MAX_STORYBOARD_TILES);
+    let (tiles, columns) = storyboard_layout(tiles, options.columns, options.tile_width, tile_height, options.format)?;
+    let interval = options.interval.max(duration / tiles as f64);
+    let tile_range = |index: usize| {
+        let start = index as f64 * interval;
+        (start, ((index + 1) as f64 * interval).min(duration).max(start))
+    };
+
+    // Each tile shows the middle of its time range, the first frame is often black
+    let frames: Vec<Result<DynamicImage, AppError>> = (0..tiles)
+        .into_par_iter()
+        .map(|index| {
+            let (start, end) = tile_range(index);
+            extract_frame(&file_path, (start + end) / 2.0, options.tile_width)
+        })
+        .collect();
+    if frames.iter().all(Result::is_err) {
+        // Every frame failed, report the first error
+        return Err(frames.into_iter().find_map(Result::err).unwrap_or_else(|| AppError::internal("No storyboard frames")));
+    }
+
+    // Frames that failed to decode stay black
+    let rows = (tiles as u32).div_ceil(columns);
+    let tile_position = |index: usize| (index as u32 % columns * options.tile_width, index as u32 / columns * tile_height);
+    let mut sprite = DynamicImage::ImageRgb8(RgbImage::new(columns * options.tile_width, rows * tile_height));
+    for (index, frame) in frames.into_iter().enumerate() {
+        if let Ok(frame) = frame {
+            let frame = frame.resize_exact(options.tile_width, tile_height, FilterType::Triangle);
+            let (x, y) = tile_position(index);
+            sprite
+                .copy_from(&frame, x, y)
+                .map_err(|e| AppError::from(e).with_path(Path::new(&file_path)))?;
+        }
+    }
+
+    let index_dir = Path::new(&index_dir);
+    let sprite_name = format!("{}_{}.{}", name, STORYBOARD_SUFFIX, options.format.extension());
+    let sprite_path = index_dir.join(&sprite_name);
+    encode_thumbnail(&sprite, &sprite_path, options.format)?;
+
+    let mut vtt = String::from("WEBVTT\n");
+    for index in 0..tiles {
+        let (start, end) = tile_range(index);
+        let (x, y) = tile_position(index);
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            vtt_timestamp(start),
+            vtt_timestamp(end),
+            sprite_name,
+            x,
+            y,
+            options.tile_width,
+            tile_height
+        ));
+    }
+    let vtt_path = index_dir.join(format!("{}_{}.vtt", name, STORYBOARD_SUFFIX));
+    fs::write(&vtt_path, vtt).map_err(|e| AppError::io(&vtt_path, e))?;
+
+    Ok(Some(Storyboard {
+        sprite_path: sprite_path.to_string_lossy().to_string(),
+        vtt_path: vtt_path.to_string_lossy().to_string(),
+        interval,
+        tile_width: options.tile_width,
+        tile_height,
+        columns,
+        tiles,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vtt_timestamp_formats_hours_minutes_seconds_and_millis() {
+        assert_eq!(vtt_timestamp(0.0), "00:00:00.000");
+        assert_eq!(vtt_timestamp(7.25), "00:00:07.250");
+        assert_eq!(vtt_timestamp(61.5), "00:01:01.500");
+        assert_eq!(vtt_timestamp(3725.042), "01:02:05.042");
+    }
+
+    #[test]
+    fn vtt_timestamp_rounds_to_the_nearest_millisecond() {
+        assert_eq!(vtt_timestamp(1.0004), "00:00:01.000");
+        assert_eq!(vtt_timestamp(1.0006), "00:00:01.001");
+        assert_eq!(vtt_timestamp(59.9996), "00:01:00.000");
+    }
+
+    #[test]
+    fn vtt_timestamp_keeps_counting_hours_past_a_day() {
+        assert_eq!(vtt_timestamp(100.0 * 3600.0), "100:00:00.000");
+    }
+
+    #[test]
+    fn storyboard_layout_keeps_small_sheets_as_requested() {
+        assert_eq!(storyboard_layout(120, 10, 160, 90, ThumbnailFormat::Jpeg).unwrap(), (120, 10));
+        assert_eq!(storyboard_layout(4, 10, 160, 90, ThumbnailFormat::Jpeg).unwrap(), (4, 4));
+    }
+
+    #[test]
+    fn storyboard_layout_fits_the_format_size_limit() {
+        // 20 columns of 1280 would be 25600 wide, 400 tiles of 720 in 12 columns 24480 high
+        let (tiles, columns) = storyboard_layout(400, 20, 1280, 720, ThumbnailFormat::WebP).unwrap();
+        assert_eq!(columns, 12);
+        assert!(columns * 1280 <= 16383);
+        assert!((tiles as u32).div_ceil(columns) * 720 <= 16383);
+    }
+
+    #[test]
+    fn storyboard_layout_fits_the_pixel_budget() {
+        for format in [ThumbnailFormat::Jpeg, ThumbnailFormat::WebP] {
+            let (tiles, columns) = storyboard_layout(400, 10, 1280, 720, format).unwrap();
+            let pixels = (columns * 1280) as u64 * ((tiles as u32).div_ceil(columns) * 720) as u64;
+            assert!(tiles > 0 && pixels <= MAX_STORYBOARD_PIXELS, "{} tiles in {} columns", tiles, columns);
+        }
+    }
+
+    #[test]
+    fn storyboard_layout_rejects_tiles_larger_than_a_sheet() {
+        assert!(storyboard_layout(10, 10, 1280, 25600, ThumbnailFormat::WebP).is_err());
+    }
+}
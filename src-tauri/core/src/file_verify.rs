@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_hash::{hash_file_contents, parse_stored_hash, HashAlgorithm};
+use crate::file_scan::ScanError;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyItem {
+    pub path: String,
+    // `Algorithm:hex` as stored in the index, or bare SHA-256 hex
+    pub expected_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HashMismatch {
+    pub path: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub verified: usize,
+    pub mismatched: Vec<HashMismatch>,
+    pub missing: Vec<PathBuf>,
+    pub unreadable: Vec<ScanError>,
+}
+
+pub enum VerifyOutcome {
+    Verified,
+    Mismatched(HashMismatch),
+    Missing(PathBuf),
+    Unreadable(ScanError),
+}
+
+// Parsed up front so a malformed expected hash fails the whole request instead of one file
+pub struct ParsedVerifyItem {
+    pub path: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub expected: String,
+}
+
+pub fn parse_verify_items(items: &[VerifyItem]) -> Result<Vec<ParsedVerifyItem>, AppError> {
+    items
+        .iter()
+        .map(|item| {
+            let (algorithm, expected) = parse_stored_hash(&item.expected_hash)?;
+            Ok(ParsedVerifyItem {
+                path: PathBuf::from(&item.path),
+                algorithm,
+                expected,
+            })
+        })
+        .collect()
+}
+
+pub fn verify_file(item: &ParsedVerifyItem) -> VerifyOutcome {
+    match hash_file_contents(&item.path, item.algorithm) {
+        Ok(actual) if actual == item.expected => VerifyOutcome::Verified,
+        Ok(actual) => VerifyOutcome::Mismatched(HashMismatch {
+            path: item.path.clone(),
+            algorithm: item.algorithm,
+            expected: item.expected.clone(),
+            actual,
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => VerifyOutcome::Missing(item.path.clone()),
+        Err(e) => VerifyOutcome::Unreadable(ScanError::from_io(&item.path, &e)),
+    }
+}
+
+// Checks every item in parallel, for callers that do not go through the job engine
+pub fn verify_files(items: &[VerifyItem]) -> Result<VerifyReport, AppError> {
+    let items = parse_verify_items(items)?;
+    let outcomes: Vec<VerifyOutcome> = items.par_iter().map(verify_file).collect();
+    Ok(outcomes.into_iter().collect())
+}
+
+impl FromIterator<VerifyOutcome> for VerifyReport {
+    fn from_iter<I: IntoIterator<Item = VerifyOutcome>>(outcomes: I) -> Self {
+        let mut report = VerifyReport::default();
+        for outcome in outcomes {
+            match outcome {
+                VerifyOutcome::Verified => report.verified += 1,
+                VerifyOutcome::Mismatched(mismatch) => report.mismatched.push(mismatch),
+                VerifyOutcome::Missing(path) => report.missing.push(path),
+                VerifyOutcome::Unreadable(error) => report.unreadable.push(error),
+            }
+        }
+        report
+    }
+}
+
+// `.json` paths get the report as JSON, anything else a line per problem in the style of `sha256sum -c`
+pub fn write_verify_report(report: &VerifyReport, path: &Path) -> Result<(), AppError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json")) {
+        serde_json::to_writer_pretty(&mut writer, report)?;
+    } else {
+        for mismatch in &report.mismatched {
+            writeln!(writer, "{}: FAILED ({:?} expected {}, got {})", mismatch.path.display(), mismatch.algorithm, mismatch.expected, mismatch.actual)?;
+        }
+        for path in &report.missing {
+            writeln!(writer, "{}: MISSING", path.display())?;
+        }
+        for error in &report.unreadable {
+            writeln!(writer, "{}: UNREADABLE ({})", error.path.display(), error.message)?;
+        }
+        writeln!(
+            writer,
+            "{} verified, {} mismatched, {} missing, {} unreadable",
+            report.verified,
+            report.mismatched.len(),
+            report.missing.len(),
+            report.unreadable.len()
+        )?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{self, EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache};
+use serde::Serialize;
+use crate::error::AppError;
+use crate::file_scan::{file_info_of, file_type_by_extension, FileInfo, FileType, ScanOptions, StorehouseFilter};
+use crate::scan_state::RenamedFile;
+
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(750);
+
+#[derive(Debug, Serialize)]
+pub struct RemovedFile {
+    pub path: PathBuf,
+    // Guessed from the extension, None when the removed path was not a media file
+    pub file_type: Option<FileType>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum WatchEvent {
+    Added(FileInfo),
+    Removed(RemovedFile),
+    Renamed(RenamedFile),
+    Modified(FileInfo),
+    // The watch failed, e.g. the kernel dropped events or the storehouse was unmounted
+    Error(AppError),
+}
+
+impl WatchEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchEvent::Added(_) => "file-added",
+            WatchEvent::Removed(_) => "file-removed",
+            WatchEvent::Renamed(_) => "file-renamed",
+            WatchEvent::Modified(_) => "file-modified",
+            WatchEvent::Error(_) => "watch-error",
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            WatchEvent::Added(info) | WatchEvent::Modified(info) => Some(&info.path),
+            WatchEvent::Removed(removed) => Some(&removed.path),
+            WatchEvent::Renamed(renamed) => Some(&renamed.to.path),
+            WatchEvent::Error(error) => error.path(),
+        }
+    }
+}
+
+pub struct StorehouseWatcher {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+}
+
+// Only files a scan of the storehouse would index are reported, files that cannot be read
+// as a watch error
+fn file_info(path: &Path, filter: &mut StorehouseFilter, events: &mut Vec<WatchEvent>) -> Option<FileInfo> {
+    if !path.is_file() || !filter.is_included(path) {
+        return None;
+    }
+    media_file_info(path, events)
+}
+
+fn media_file_info(path: &Path, events: &mut Vec<WatchEvent>) -> Option<FileInfo> {
+    match file_info_of(path) {
+        Ok(info) => info,
+        Err(e) => {
+            events.push(WatchEvent::Error(AppError::io(path, e)));
+            None
+        }
+    }
+}
+
+fn media_files_in(dir: &Path, filter: &mut StorehouseFilter, events: &mut Vec<WatchEvent>) -> Vec<FileInfo> {
+    filter.files_in(dir).iter().filter_map(|path| media_file_info(path, events)).collect()
+}
+
+fn added_events(path: &Path, filter: &mut StorehouseFilter, events: &mut Vec<WatchEvent>) {
+    if path.is_dir() {
+        let added = media_files_in(path, filter, events);
+        events.extend(added.into_iter().map(WatchEvent::Added));
+    } else if let Some(info) = file_info(path, filter, events) {
+        events.push(WatchEvent::Added(info));
+    }
+}
+
+// A removed directory can no longer be listed, so every known file below it is reported instead
+fn removed_events(path: &Path, filter: &mut StorehouseFilter, known: &BTreeSet<PathBuf>, events: &mut Vec<WatchEvent>) {
+    let below: Vec<&PathBuf> = known
+        .range(path.to_path_buf()..)
+        .take_while(|known| known.starts_with(path))
+        .collect();
+    if !below.is_empty() {
+        events.extend(below.into_iter().map(|path| removed_event(path)));
+    } else if filter.is_included(path) {
+        events.push(removed_event(path));
+    }
+}
+
+fn removed_event(path: &Path) -> WatchEvent {
+    WatchEvent::Removed(RemovedFile {
+        path: path.to_path_buf(),
+        file_type: file_type_by_extension(path),
+    })
+}
+
+fn renamed_events(from: &Path, to: &Path, filter: &mut StorehouseFilter, known: &BTreeSet<PathBuf>, events: &mut Vec<WatchEvent>) {
+    if to.is_dir() {
+        for info in media_files_in(to, filter, events) {
+            let relative = info.path.strip_prefix(to).unwrap_or(&info.path);
+            events.push(WatchEvent::Renamed(RenamedFile {
+                from: from.join(relative),
+                to: info,
+            }));
+        }
+        return;
+    }
+
+    // Renames across media and non-media or excluded names, e.g. a finished `.part` download
+    let from_type = if filter.is_included(from) { file_type_by_extension(from) } else { None };
+    match (from_type, file_info(to, filter, events)) {
+        (Some(_), Some(info)) => events.push(WatchEvent::Renamed(RenamedFile {
+            from: from.to_path_buf(),
+            to: info,
+        })),
+        (None, Some(info)) => events.push(WatchEvent::Added(info)),
+        (Some(_), None) => removed_events(from, filter, known, events),
+        (None, None) => {}
+    }
+}
+
+// Keeps the known files in step with the reported events
+fn update_known_files(events: &[WatchEvent], known: &mut BTreeSet<PathBuf>) {
+    for event in events {
+        match event {
+            WatchEvent::Added(info) | WatchEvent::Modified(info) => {
+                known.insert(info.path.clone());
+            }
+            WatchEvent::Removed(removed) => {
+                known.remove(&removed.path);
+            }
+            WatchEvent::Renamed(renamed) => {
+                known.remove(&renamed.from);
+                known.insert(renamed.to.path.clone());
+            }
+            WatchEvent::Error(_) => {}
+        }
+    }
+}
+
+fn categorize_events(debounced: Vec<DebouncedEvent>, filter: &mut StorehouseFilter, known: &mut BTreeSet<PathBuf>) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+
+    // `.tagignore` files may have changed since the last batch
+    filter.reload();
+    for event in debounced {
+        let paths = &event.paths;
+        match event.kind {
+            EventKind::Create(_) => paths.iter().for_each(|path| added_events(path, filter, &mut events)),
+            EventKind::Remove(_) => paths.iter().for_each(|path| removed_events(path, filter, known, &mut events)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                renamed_events(&paths[0], &paths[1], filter, known, &mut events)
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                paths.iter().for_each(|path| removed_events(path, filter, known, &mut events))
+            }
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                paths.iter().for_each(|path| added_events(path, filter, &mut events))
+            }
+            EventKind::Modify(ModifyKind::Data(_)) | EventKind::Modify(ModifyKind::Any) => {
+                for path in paths {
+                    if let Some(info) = file_info(path, filter, &mut events) {
+                        events.push(WatchEvent::Modified(info));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A file that was just added is not also reported as modified in the same batch, and a file
+    // removed along with its directory only once
+    let mut reported: HashSet<PathBuf> = events
+        .iter()
+        .filter(|event| matches!(event, WatchEvent::Added(_) | WatchEvent::Renamed(_)))
+        .filter_map(|event| event.path().map(Path::to_path_buf))
+        .collect();
+    let mut removed = HashSet::new();
+    events.retain(|event| match (event, event.path()) {
+        (WatchEvent::Modified(_), Some(path)) => reported.insert(path.to_path_buf()),
+        (WatchEvent::Removed(_), Some(path)) => removed.insert(path.to_path_buf()),
+        _ => true,
+    });
+
+    update_known_files(&events, known);
+    events
+}
+
+fn watch_error(dir: &Path, error: notify::Error) -> AppError {
+    match error.kind {
+        notify::ErrorKind::Io(e) => AppError::io(dir, e),
+        notify::ErrorKind::PathNotFound => AppError::NotFound {
+            path: Some(dir.to_path_buf()),
+            message: "The directory to watch does not exist".to_string(),
+        },
+        kind => AppError::internal(notify::Error::new(kind).to_string()),
+    }
+}
+
+// Applies the same scan options and `.tagignore` rules as a scan of the storehouse.
+// Lists the storehouse once up front, so setting up a watch takes about as long as a scan.
+pub fn watch_storehouse<F>(dir: &str, options: &ScanOptions, mut on_event: F) -> Result<StorehouseWatcher, AppError>
+where
+    F: FnMut(WatchEvent) + Send + 'static,
+{
+    let root = PathBuf::from(dir);
+    let mut filter = StorehouseFilter::new(&root, options)?;
+    let mut known: BTreeSet<PathBuf> = filter.files_in(&root).into_iter().collect();
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, None, move |result: DebounceEventResult| match result {
+        Ok(debounced) => categorize_events(debounced, &mut filter, &mut known).into_iter().for_each(&mut on_event),
+        Err(errors) => errors
+            .into_iter()
+            .map(|error| {
+                let path = error.paths.first().cloned().unwrap_or_else(|| root.clone());
+                WatchEvent::Error(watch_error(&path, error))
+            })
+            .for_each(&mut on_event),
+    })
+    .map_err(|e| watch_error(Path::new(dir), e))?;
+
+    debouncer
+        .watch(Path::new(dir), RecursiveMode::Recursive)
+        .map_err(|e| watch_error(Path::new(dir), e))?;
+
+    Ok(StorehouseWatcher {
+        _debouncer: debouncer,
+    })
+}
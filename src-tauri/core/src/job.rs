@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::AppError;
+use crate::file_hash::{hash_file_with_mode, FileHashResult, HashAlgorithm, HashBatchReport, HashMode};
+use crate::file_scan::{file_info_of, ScanError, ScanOptions, ScanReport, StorehouseWalker};
+use crate::file_thumbnail::{create_thumbnail, CoverSelection};
+use crate::file_verify::{parse_verify_items, verify_file, write_verify_report, VerifyItem, VerifyReport};
+
+pub type JobId = u64;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+const FINISHED_JOBS_KEPT: usize = 16;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ThumbnailRequest {
+    pub cover_name: String,
+    pub file_path: String,
+    pub frame_number: Option<usize>,
+    pub time: Option<f64>,
+    #[serde(default)]
+    pub cover_selection: CoverSelection,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThumbnailResult {
+    pub cover_name: String,
+    pub file_path: String,
+    pub thumbnail_path: Option<String>,
+    pub error: Option<AppError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind")]
+pub enum JobRequest {
+    Scan {
+        dir: String,
+        #[serde(default)]
+        options: ScanOptions,
+    },
+    HashBatch {
+        paths: Vec<String>,
+        #[serde(default)]
+        algorithm: HashAlgorithm,
+        #[serde(default)]
+        mode: HashMode,
+        // Background batches run one file at a time so they never hold up interactive jobs
+        #[serde(default)]
+        background: bool,
+    },
+    ThumbnailBatch {
+        index_dir: String,
+        items: Vec<ThumbnailRequest>,
+    },
+    Verify {
+        items: Vec<VerifyItem>,
+        // Also written to this file once every item was checked
+        #[serde(default)]
+        report_path: Option<String>,
+        #[serde(default)]
+        background: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum JobKind {
+    Scan,
+    HashBatch,
+    ThumbnailBatch,
+    Verify,
+}
+
+impl JobRequest {
+    pub fn kind(&self) -> JobKind {
+        match self {
+            JobRequest::Scan { .. } => JobKind::Scan,
+            JobRequest::HashBatch { .. } => JobKind::HashBatch,
+            JobRequest::ThumbnailBatch { .. } => JobKind::ThumbnailBatch,
+            JobRequest::Verify { .. } => JobKind::Verify,
+        }
+    }
+
+    fn is_background(&self) -> bool {
+        matches!(
+            self,
+            JobRequest::HashBatch { background: true, .. } | JobRequest::Verify { background: true, .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobProgress {
+    pub id: JobId,
+    pub kind: JobKind,
+    pub state: JobState,
+    pub done: usize,
+    pub total: usize,
+    pub current_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub progress: JobProgress,
+    pub result: Option<Value>,
+    pub error: Option<AppError>,
+}
+
+struct Job {
+    status: JobStatus,
+    cancelled: Arc<AtomicBool>,
+}
+
+type JobTable = Arc<Mutex<HashMap<JobId, Job>>>;
+type ProgressSink = Arc<dyn Fn(&JobProgress) + Send + Sync>;
+
+fn lock(jobs: &JobTable) -> MutexGuard<'_, HashMap<JobId, Job>> {
+    jobs.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Handed to the work of a running job to report progress and observe cancellation
+pub struct JobContext {
+    id: JobId,
+    kind: JobKind,
+    cancelled: Arc<AtomicBool>,
+    jobs: JobTable,
+    sink: ProgressSink,
+    pool: Arc<ThreadPool>,
+    done: AtomicUsize,
+    total: AtomicUsize,
+    last_emit: Mutex<Instant>,
+}
+
+impl JobContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.emit(JobState::Running, None);
+    }
+
+    pub fn advance(&self, current_path: &Path) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let finished_batch = done >= self.total.load(Ordering::Relaxed);
+
+        // Large batches would otherwise flood the window with events
+        let mut last_emit = self.last_emit.lock().unwrap_or_else(|e| e.into_inner());
+        if finished_batch || last_emit.elapsed() >= PROGRESS_INTERVAL {
+            *last_emit = Instant::now();
+            drop(last_emit);
+            self.emit(JobState::Running, Some(current_path.to_string_lossy().to_string()));
+        }
+    }
+
+    fn progress(&self, state: JobState, current_path: Option<String>) -> JobProgress {
+        JobProgress {
+            id: self.id,
+            kind: self.kind,
+            state,
+            done: self.done.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            current_path,
+        }
+    }
+
+    fn emit(&self, state: JobState, current_path: Option<String>) {
+        let progress = self.progress(state, current_path);
+        if let Some(job) = lock(&self.jobs).get_mut(&self.id) {
+            job.status.progress = progress.clone();
+        }
+        (self.sink)(&progress);
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        Ok(())
+    }
+
+    // Runs `work` for every item on the worker pool
+    pub fn run_batch<T, R, P, F>(&self, items: &[T], path_of: P, work: F) -> Result<Vec<R>, AppError>
+    where
+        T: Sync,
+        R: Send,
+        P: Fn(&T) -> &Path + Sync,
+        F: Fn(&T) -> R + Sync,
+    {
+        let results: Vec<Option<R>> = self.pool.install(|| {
+            items
+                .par_iter()
+                .map(|item| {
+                    if self.is_cancelled() {
+                        return None;
+                    }
+                    let result = work(item);
+                    self.advance(path_of(item));
+                    Some(result)
+                })
+                .collect()
+        });
+
+        self.check_cancelled()?;
+        results.into_iter().collect::<Option<_>>().ok_or(AppError::Cancelled)
+    }
+}
+
+pub struct JobManager {
+    pool: Arc<ThreadPool>,
+    background_pool: Arc<ThreadPool>,
+    jobs: JobTable,
+    next_id: AtomicU64,
+}
+
+impl JobManager {
+    pub fn new(workers: usize) -> Result<Self, AppError> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|index| format!("job-worker-{}", index))
+            .build()
+            .map_err(|e| AppError::internal(e.to_string()))?;
+        let background_pool = ThreadPoolBuilder::new()
+            .num_threads(1)
+            .thread_name(|_| "job-background".to_string())
+            .build()
+            .map_err(|e| AppError::internal(e.to_string()))?;
+
+        Ok(JobManager {
+            pool: Arc::new(pool),
+            background_pool: Arc::new(background_pool),
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    pub fn submit<F>(&self, request: JobRequest, on_progress: F) -> JobId
+    where
+        F: Fn(&JobProgress) + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let pool = if request.is_background() {
+            self.background_pool.clone()
+        } else {
+            self.pool.clone()
+        };
+        let context = JobContext {
+            id,
+            kind: request.kind(),
+            cancelled: cancelled.clone(),
+            jobs: self.jobs.clone(),
+            sink: Arc::new(on_progress),
+            pool,
+            done: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            last_emit: Mutex::new(Instant::now()),
+        };
+
+        {
+            let mut jobs = lock(&self.jobs);
+            prune_finished_jobs(&mut jobs);
+            jobs.insert(id, Job {
+                status: JobStatus {
+                    progress: context.progress(JobState::Running, None),
+                    result: None,
+                    error: None,
+                },
+                cancelled,
+            });
+        }
+
+        // The coordinating thread only waits on the pool, so it does not count against the worker bound
+        thread::spawn(move || {
+            let outcome = catch_panic(|| execute(&request, &context));
+            let state = match &outcome {
+                Ok(_) => JobState::Completed,
+                Err(AppError::Cancelled) => JobState::Cancelled,
+                Err(_) => JobState::Failed,
+            };
+            let progress = context.progress(state, None);
+
+            if let Some(job) = lock(&context.jobs).get_mut(&id) {
+                job.status.progress = progress.clone();
+                match outcome {
+                    Ok(result) => job.status.result = Some(result),
+                    Err(AppError::Cancelled) => {}
+                    Err(e) => job.status.error = Some(e),
+                }
+            }
+            (context.sink)(&progress);
+        });
+
+        id
+    }
+
+    pub fn cancel(&self, id: JobId) -> Result<(), AppError> {
+        let jobs = lock(&self.jobs);
+        let job = jobs.get(&id).ok_or_else(|| AppError::invalid_input(format!("Unknown job {}", id)))?;
+        job.cancelled.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn status(&self, id: JobId) -> Result<JobStatus, AppError> {
+        lock(&self.jobs)
+            .get(&id)
+            .map(|job| job.status.clone())
+            .ok_or_else(|| AppError::invalid_input(format!("Unknown job {}", id)))
+    }
+}
+
+fn prune_finished_jobs(jobs: &mut HashMap<JobId, Job>) {
+    let mut finished: Vec<JobId> = jobs
+        .iter()
+        .filter(|(_, job)| job.status.progress.state != JobState::Running)
+        .map(|(id, _)| *id)
+        .collect();
+    if finished.len() <= FINISHED_JOBS_KEPT {
+        return;
+    }
+    finished.sort_unstable();
+    for id in &finished[..finished.len() - FINISHED_JOBS_KEPT] {
+        jobs.remove(id);
+    }
+}
+
+// A job that panics fails like any other instead of leaving its status running forever
+fn catch_panic<F>(work: F) -> Result<Value, AppError>
+where
+    F: FnOnce() -> Result<Value, AppError>,
+{
+    panic::catch_unwind(AssertUnwindSafe(work)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "The job panicked".to_string());
+        Err(AppError::internal(message))
+    })
+}
+
+fn to_value<T: Serialize>(result: T) -> Result<Value, AppError> {
+    Ok(serde_json::to_value(result)?)
+}
+
+// Fails with `AppError::Cancelled` once the job was cancelled
+fn execute(request: &JobRequest, context: &JobContext) -> Result<Value, AppError> {
+    match request {
+        JobRequest::Scan { dir, options } => {
+            let mut report = ScanReport::default();
+            let mut walker = StorehouseWalker::new(Path::new(dir), options)?;
+            let mut paths = Vec::new();
+            for entry in &mut walker {
+                context.check_cancelled()?;
+                match entry {
+                    Ok(entry) => paths.push(entry.into_path()),
+                    Err(e) => report.errors.push(e),
+                }
+            }
+            report.skipped = walker.skipped();
+
+            context.set_total(paths.len());
+            let file_infos = context.run_batch(&paths, |path| path.as_path(), |path| file_info_of(path))?;
+            for (path, file_info) in paths.iter().zip(file_infos) {
+                match file_info {
+                    Ok(Some(file_info)) => report.files.push(file_info),
+                    Ok(None) => report.skipped += 1,
+                    Err(e) => report.errors.push(ScanError::from_io(path, &e)),
+                }
+            }
+            to_value(report)
+        }
+        JobRequest::HashBatch { paths, algorithm, mode, .. } => {
+            let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+            context.set_total(paths.len());
+            let hashes = context.run_batch(&paths, |path| path.as_path(), |path| hash_file_with_mode(path, *algorithm, *mode))?;
+
+            let mut report = HashBatchReport::default();
+            for (path, hash) in paths.into_iter().zip(hashes) {
+                match hash {
+                    Ok(hash) => report.results.push(FileHashResult {
+                        path: path.to_string_lossy().to_string(),
+                        hash,
+                        algorithm: *algorithm,
+                        mode: *mode,
+                    }),
+                    Err(e) => report.errors.push(ScanError::from_io(&path, &e)),
+                }
+            }
+            to_value(report)
+        }
+        JobRequest::ThumbnailBatch { index_dir, items } => {
+            context.set_total(items.len());
+            let results = context.run_batch(
+                items,
+                |item| Path::new(&item.file_path),
+                |item| {
+                    let thumbnail = create_thumbnail(
+                        item.cover_name.clone(),
+                        item.file_path.clone(),
+                        index_dir.clone(),
+                        item.frame_number,
+                        item.time,
+                        item.cover_selection,
+                    );
+                    let (thumbnail_path, error) = match thumbnail {
+                        Ok(thumbnail_path) => (thumbnail_path, None),
+                        Err(e) => (None, Some(e)),
+                    };
+                    ThumbnailResult {
+                        cover_name: item.cover_name.clone(),
+                        file_path: item.file_path.clone(),
+                        thumbnail_path,
+                        error,
+                    }
+                },
+            )?;
+            to_value(results)
+        }
+        JobRequest::Verify { items, report_path, .. } => {
+            let items = parse_verify_items(items)?;
+            context.set_total(items.len());
+            let outcomes = context.run_batch(&items, |item| item.path.as_path(), verify_file)?;
+
+            let report: VerifyReport = outcomes.into_iter().collect();
+            if let Some(report_path) = report_path {
+                write_verify_report(&report, Path::new(report_path))?;
+            }
+            to_value(report)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Files to hash that are removed again when the test ends
+    struct Files {
+        dir: PathBuf,
+    }
+
+    impl Files {
+        fn new(name: &str, count: usize) -> Self {
+            let dir = std::env::temp_dir().join(format!("tag-anywhere-job-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            for index in 0..count {
+                fs::write(dir.join(format!("{}.bin", index)), format!("file {}", index)).unwrap();
+            }
+            Files { dir }
+        }
+
+        fn hash_batch(&self, background: bool) -> JobRequest {
+            let mut paths: Vec<String> = fs::read_dir(&self.dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path().to_string_lossy().to_string())
+                .collect();
+            paths.sort();
+            JobRequest::HashBatch {
+                paths,
+                algorithm: HashAlgorithm::Sha256,
+                mode: HashMode::Full,
+                background,
+            }
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn wait_for(jobs: &JobManager, id: JobId) -> JobStatus {
+        let started = Instant::now();
+        loop {
+            let status = jobs.status(id).unwrap();
+            if status.progress.state != JobState::Running {
+                return status;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "job {} did not finish", id);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn counts_progress_up_to_the_total() {
+        let files = Files::new("progress", 5);
+        let jobs = JobManager::new(2).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let id = jobs.submit(files.hash_batch(false), move |progress| {
+            sink.lock().unwrap().push(progress.clone());
+        });
+
+        let status = wait_for(&jobs, id);
+        assert_eq!(status.progress.state, JobState::Completed);
+        assert_eq!((status.progress.done, status.progress.total), (5, 5));
+        assert_eq!(status.result.unwrap()["results"].as_array().unwrap().len(), 5);
+
+        // The final event is sent after the status is updated, so it may still be on its way
+        thread::sleep(Duration::from_millis(50));
+        let events = events.lock().unwrap();
+        let last = events.last().unwrap();
+        assert_eq!((last.state, last.done, last.total), (JobState::Completed, 5, 5));
+        assert!(events.windows(2).all(|pair| pair[0].done <= pair[1].done));
+    }
+
+    #[test]
+    fn cancelled_jobs_skip_the_remaining_items() {
+        let files = Files::new("cancel", 20);
+        let jobs = Arc::new(JobManager::new(2).unwrap());
+        let canceller = Arc::downgrade(&jobs);
+        // Cancelled as soon as the total is known, before any file was hashed
+        let id = jobs.submit(files.hash_batch(false), move |progress| {
+            if progress.state == JobState::Running && progress.total > 0 && progress.done == 0 {
+                if let Some(jobs) = canceller.upgrade() {
+                    jobs.cancel(progress.id).unwrap();
+                }
+            }
+        });
+
+        let status = wait_for(&jobs, id);
+        assert_eq!(status.progress.state, JobState::Cancelled);
+        assert_eq!(status.progress.done, 0);
+        assert!(status.result.is_none() && status.error.is_none());
+    }
+
+    #[test]
+    fn background_jobs_run_on_the_background_pool() {
+        let files = Files::new("background", 3);
+        let jobs = JobManager::new(2).unwrap();
+        for (background, expected) in [(true, "job-background"), (false, "job-worker-")] {
+            let threads = Arc::new(Mutex::new(Vec::new()));
+            let sink = threads.clone();
+            let id = jobs.submit(files.hash_batch(background), move |progress| {
+                // Items report their progress from the thread they ran on
+                if progress.current_path.is_some() {
+                    sink.lock().unwrap().push(thread::current().name().unwrap_or_default().to_string());
+                }
+            });
+
+            assert_eq!(wait_for(&jobs, id).progress.state, JobState::Completed);
+            let threads = threads.lock().unwrap();
+            assert!(!threads.is_empty());
+            assert!(threads.iter().all(|name| name.starts_with(expected)), "{:?}", threads);
+        }
+    }
+
+    #[test]
+    fn panics_fail_the_job() {
+        let outcome = catch_panic(|| panic!("worker exploded"));
+        match outcome {
+            Err(AppError::Internal { message }) => assert_eq!(message, "worker exploded"),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+pub mod file_scan;
+pub mod scan_state;
+pub mod file_watcher;
+pub mod file_hash;
+pub mod file_duplicates;
+pub mod file_verify;
+pub mod file_manifest;
+pub mod file_relink;
+pub mod perceptual_hash;
+pub mod file_thumbnail;
+pub mod thumbnail_cache;
+pub mod file_operation;
+pub mod video_frame;
+pub mod video_fingerprint;
+pub mod file_stats;
+pub mod tool_resolver;
+pub mod job;
+pub mod error;
\ No newline at end of file
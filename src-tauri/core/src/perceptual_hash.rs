@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use image::{imageops::{self, FilterType}, DynamicImage, GrayImage, ImageReader};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_scan::{file_type_of, FileType, ScanError, ScanOptions, StorehouseWalker};
+
+const PHASH_SIZE: usize = 32;
+const PHASH_LOW_FREQUENCIES: usize = 8;
+pub const DEFAULT_SIMILARITY_THRESHOLD: u32 = 10;
+
+// Both produce 64 bit hashes compared by Hamming distance. dHash is cheaper,
+// pHash is more robust against re-encoding and small colour changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum PerceptualHashKind {
+    #[default]
+    DHash,
+    PHash,
+}
+
+impl PerceptualHashKind {
+    pub fn compute(&self, image: &DynamicImage) -> u64 {
+        match self {
+            PerceptualHashKind::DHash => dhash(image),
+            PerceptualHashKind::PHash => phash(image),
+        }
+    }
+}
+
+fn grayscale(image: &DynamicImage, width: u32, height: u32) -> GrayImage {
+    imageops::resize(&image.to_luma8(), width, height, FilterType::Triangle)
+}
+
+// One bit per horizontally adjacent pixel pair of a 9x8 thumbnail: is the left one brighter
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let pixels = grayscale(image, 9, 8);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let bit = pixels.get_pixel(x, y)[0] > pixels.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | bit as u64;
+        }
+    }
+    hash
+}
+
+// One bit per low frequency DCT coefficient of a 32x32 thumbnail: is it above the median
+pub fn phash(image: &DynamicImage) -> u64 {
+    let pixels = grayscale(image, PHASH_SIZE as u32, PHASH_SIZE as u32);
+
+    // Only the 8x8 lowest frequencies are needed, so the separable DCT is computed directly
+    let mut cosines = [[0f64; PHASH_SIZE]; PHASH_LOW_FREQUENCIES];
+    for (u, row) in cosines.iter_mut().enumerate() {
+        for (x, cosine) in row.iter_mut().enumerate() {
+            *cosine = ((2 * x + 1) as f64 * u as f64 * PI / (2 * PHASH_SIZE) as f64).cos();
+        }
+    }
+
+    let mut rows = [[0f64; PHASH_LOW_FREQUENCIES]; PHASH_SIZE];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = (0..PHASH_SIZE)
+                .map(|x| pixels.get_pixel(x as u32, y as u32)[0] as f64 * cosines[u][x])
+                .sum();
+        }
+    }
+
+    let mut coefficients = Vec::with_capacity(PHASH_LOW_FREQUENCIES * PHASH_LOW_FREQUENCIES);
+    for cosine in &cosines {
+        coefficients.extend((0..PHASH_LOW_FREQUENCIES).map(|u| {
+            rows.iter().zip(cosine).map(|(row, cosine)| cosine * row[u]).sum::<f64>()
+        }));
+    }
+
+    // The DC coefficient is the mean brightness and far above the others, it would skew the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .fold(0u64, |hash, coefficient| (hash << 1) | (*coefficient > median) as u64)
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn decode_image(path: &Path) -> Result<DynamicImage, AppError> {
+    ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| AppError::io(path, e))?
+        .decode()
+        .map_err(|e| AppError::from(e).with_path(path))
+}
+
+pub fn perceptual_hash_file(path: &Path, kind: PerceptualHashKind) -> Result<u64, AppError> {
+    Ok(kind.compute(&decode_image(path)?))
+}
+
+// Metric tree over any distance satisfying the triangle inequality, so a query only
+// visits children whose edge distance lies within the threshold of the query distance
+pub struct BkTree<T> {
+    nodes: Vec<BkNode<T>>,
+    distance: fn(&T, &T) -> u32,
+}
+
+struct BkNode<T> {
+    value: T,
+    index: usize,
+    children: HashMap<u32, usize>,
+}
+
+impl<T> BkTree<T> {
+    pub fn new(distance: fn(&T, &T) -> u32) -> Self {
+        BkTree {
+            nodes: Vec::new(),
+            distance,
+        }
+    }
+
+    // `index` identifies the value in the caller's collection and is returned by `find`
+    pub fn insert(&mut self, value: T, index: usize) {
+        let new_node = self.nodes.len();
+        if new_node == 0 {
+            self.nodes.push(BkNode { value, index, children: HashMap::new() });
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let distance = (self.distance)(&self.nodes[current].value, &value);
+            match self.nodes[current].children.get(&distance) {
+                Some(child) => current = *child,
+                None => {
+                    self.nodes[current].children.insert(distance, new_node);
+                    self.nodes.push(BkNode { value, index, children: HashMap::new() });
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn find(&self, value: &T, threshold: u32) -> Vec<(usize, u32)> {
+        let mut found = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+
+        let mut pending = vec![0];
+        while let Some(current) = pending.pop() {
+            let node = &self.nodes[current];
+            let distance = (self.distance)(&node.value, value);
+            if distance <= threshold {
+                found.push((node.index, distance));
+            }
+            for (edge, child) in &node.children {
+                if edge.abs_diff(distance) <= threshold {
+                    pending.push(*child);
+                }
+            }
+        }
+        found
+    }
+}
+
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+// Groups items whose distance chains stay within the threshold (single linkage)
+pub fn cluster_within<T: Copy>(values: &[T], distance: fn(&T, &T) -> u32, threshold: u32) -> Vec<Vec<usize>> {
+    let mut tree = BkTree::new(distance);
+    for (index, value) in values.iter().enumerate() {
+        tree.insert(*value, index);
+    }
+
+    let mut parents: Vec<usize> = (0..values.len()).collect();
+    for (index, value) in values.iter().enumerate() {
+        for (other, _) in tree.find(value, threshold) {
+            let a = find_root(&mut parents, index);
+            let b = find_root(&mut parents, other);
+            if a != b {
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..values.len() {
+        let root = find_root(&mut parents, index);
+        clusters.entry(root).or_default().push(index);
+    }
+    clusters.into_values().filter(|cluster| cluster.len() > 1).collect()
+}
+
+struct HashedImage {
+    hash: u64,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PerceptualHashResult {
+    pub path: String,
+    pub hash: String,
+    pub kind: PerceptualHashKind,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarImage {
+    pub path: PathBuf,
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarImageGroup {
+    // Largest resolution first, as that is usually the copy worth keeping
+    pub images: Vec<SimilarImage>,
+    pub max_distance: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SimilarImagesReport {
+    pub groups: Vec<SimilarImageGroup>,
+    pub errors: Vec<ScanError>,
+}
+
+pub fn read_single_image_and_hash(file_path: String, kind: PerceptualHashKind) -> Result<PerceptualHashResult, AppError> {
+    let hash = perceptual_hash_file(Path::new(&file_path), kind)?;
+    Ok(PerceptualHashResult {
+        path: file_path,
+        hash: format_hash(hash),
+        kind,
+    })
+}
+
+pub fn find_similar_image_files(
+  dir_path: String,
+  options: ScanOptions,
+  kind: PerceptualHashKind,
+  threshold: u32,
+) -> Result<SimilarImagesReport, AppError> {
+    let mut report = SimilarImagesReport::default();
+
+    let mut paths = Vec::new();
+    for entry in StorehouseWalker::new(Path::new(&dir_path), &options)? {
+        match entry {
+            Ok(entry) => paths.push(entry.into_path()),
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    // Images are hashed as soon as they are decoded so only one per worker is held in memory
+    let hashed: Vec<(PathBuf, Result<HashedImage, AppError>)> = paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let hashed = match file_type_of(&path) {
+                Ok(Some(FileType::Image)) => decode_image(&path).map(|image| HashedImage {
+                    hash: kind.compute(&image),
+                    width: image.width(),
+                    height: image.height(),
+                }),
+                Ok(_) => return None,
+                Err(e) => Err(AppError::io(&path, e)),
+            };
+            Some((path, hashed))
+        })
+        .collect();
+
+    let mut images = Vec::new();
+    for (path, hashed) in hashed {
+        match hashed {
+            Ok(hashed) => images.push((path, hashed)),
+            Err(e) => report.errors.push(ScanError::from_app_error(&path, &e)),
+        }
+    }
+
+    let hashes: Vec<u64> = images.iter().map(|(_, image)| image.hash).collect();
+    for cluster in cluster_within(&hashes, |a, b| hamming_distance(*a, *b), threshold) {
+        let mut max_distance = 0;
+        for (i, a) in cluster.iter().enumerate() {
+            for b in &cluster[i + 1..] {
+                max_distance = max_distance.max(hamming_distance(hashes[*a], hashes[*b]));
+            }
+        }
+
+        let mut group: Vec<SimilarImage> = cluster
+            .into_iter()
+            .map(|index| {
+                let (path, image) = &images[index];
+                SimilarImage {
+                    path: path.clone(),
+                    hash: format_hash(image.hash),
+                    width: image.width,
+                    height: image.height,
+                }
+            })
+            .collect();
+        group.sort_by(|a, b| {
+            (b.width as u64 * b.height as u64)
+                .cmp(&(a.width as u64 * a.height as u64))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        report.groups.push(SimilarImageGroup {
+            images: group,
+            max_distance,
+        });
+    }
+    report.groups.sort_by(|a, b| a.images[0].path.cmp(&b.images[0].path));
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: &u64, b: &u64) -> u32 {
+        hamming_distance(*a, *b)
+    }
+
+    // Deterministic values spread over the whole hash space
+    fn pseudo_random_hashes(count: usize) -> Vec<u64> {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bk_tree_finds_the_same_values_as_a_linear_scan() {
+        let mut hashes = pseudo_random_hashes(200);
+        // Near copies of a few values, so some queries match more than themselves
+        let near_copies: Vec<u64> = hashes[..20].iter().map(|hash| hash ^ 0b1011).collect();
+        hashes.extend(near_copies);
+
+        let mut tree = BkTree::new(distance);
+        for (index, hash) in hashes.iter().enumerate() {
+            tree.insert(*hash, index);
+        }
+
+        for threshold in [0, 3, 10, 24] {
+            for query in &hashes[..40] {
+                let mut found = tree.find(query, threshold);
+                found.sort();
+                let expected: Vec<(usize, u32)> = hashes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, hash)| (index, hamming_distance(*query, *hash)))
+                    .filter(|(_, distance)| *distance <= threshold)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn bk_tree_keeps_duplicate_values() {
+        let mut tree = BkTree::new(distance);
+        tree.insert(42, 0);
+        tree.insert(42, 1);
+        tree.insert(43, 2);
+
+        let mut found = tree.find(&42, 0);
+        found.sort();
+        assert_eq!(found, vec![(0, 0), (1, 0)]);
+        assert!(BkTree::new(distance).find(&42, 64).is_empty());
+    }
+
+    #[test]
+    fn cluster_within_links_chains_and_drops_singletons() {
+        // 0 and 2 are 2 apart, but both are within 1 of the value between them
+        let values = [0b000, 0b001, 0b011, u64::MAX, u64::MAX ^ 1, 0xff00];
+        let mut clusters = cluster_within(&values, distance, 1);
+        for cluster in &mut clusters {
+            cluster.sort();
+        }
+        clusters.sort();
+        assert_eq!(clusters, vec![vec![0, 1, 2], vec![3, 4]]);
+    }
+
+    #[test]
+    fn cluster_within_threshold_zero_groups_identical_values() {
+        let values = [7, 8, 7, 9, 8];
+        let mut clusters = cluster_within(&values, distance, 0);
+        for cluster in &mut clusters {
+            cluster.sort();
+        }
+        clusters.sort();
+        assert_eq!(clusters, vec![vec![0, 2], vec![1, 4]]);
+    }
+}
@@ -0,0 +1,492 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_hash::{sample_hash_file_contents, HashAlgorithm};
+use crate::file_scan::{file_info_of, FileInfo, FileType, ScanError, ScanOptions, StorehouseWalker};
+
+const SCAN_STATE_FILE_NAME: &str = "scan-state.json";
+// Written by a scan and only promoted to the scan state once the caller applied the delta
+const PENDING_SCAN_STATE_FILE_NAME: &str = "scan-state.pending.json";
+// Only compared against other cache entries, so the fastest algorithm is used
+pub const SAMPLE_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Xxh3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileId {
+    pub device: u64,
+    pub inode: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanStateEntry {
+    pub size: u64,
+    pub modified_ns: u64,
+    pub file_id: Option<FileId>,
+    // Cached so unchanged files are not sniffed again; None for non-media files
+    pub file_type: Option<FileType>,
+    // Sampled content hash of media files, missing in caches written before it existed
+    #[serde(default)]
+    pub sample_hash: Option<String>,
+}
+
+impl ScanStateEntry {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let modified_ns = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+
+        ScanStateEntry {
+            size: metadata.len(),
+            modified_ns,
+            file_id: file_id(metadata),
+            file_type: None,
+            sample_hash: None,
+        }
+    }
+
+    pub fn is_unchanged(&self, other: &ScanStateEntry) -> bool {
+        self.size == other.size && self.modified_ns == other.modified_ns && self.file_id == other.file_id
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileId {
+        device: metadata.dev(),
+        inode: metadata.ino(),
+    })
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanState {
+    pub root: PathBuf,
+    pub entries: HashMap<PathBuf, ScanStateEntry>,
+    // Media files reported as removed, kept so relinking can still narrow candidates
+    // by their size and sampled hash. Dropped once the path exists again.
+    #[serde(default)]
+    pub removed: HashMap<PathBuf, ScanStateEntry>,
+}
+
+impl ScanState {
+    pub fn path(index_dir: &Path) -> PathBuf {
+        index_dir.join(SCAN_STATE_FILE_NAME)
+    }
+
+    pub fn pending_path(index_dir: &Path) -> PathBuf {
+        index_dir.join(PENDING_SCAN_STATE_FILE_NAME)
+    }
+
+    pub fn read(index_dir: &Path) -> Option<Self> {
+        Self::read_from(&Self::path(index_dir))
+    }
+
+    fn read_from(path: &Path) -> Option<Self> {
+        File::open(path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, ScanState>(BufReader::new(file)).ok())
+    }
+
+    // A missing, unreadable or foreign cache simply means every file is reported as new
+    pub fn load(index_dir: &Path, root: &Path) -> Self {
+        match Self::read(index_dir) {
+            Some(state) if state.root == root => state,
+            _ => ScanState {
+                root: root.to_path_buf(),
+                entries: HashMap::new(),
+                removed: HashMap::new(),
+            },
+        }
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<(), AppError> {
+        self.write_to(index_dir, &Self::path(index_dir))
+    }
+
+    fn write_to(&self, index_dir: &Path, path: &Path) -> Result<(), AppError> {
+        fs::create_dir_all(index_dir)?;
+        let temp_path = path.with_extension("json.tmp");
+
+        let writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(writer, self)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenamedFile {
+    pub from: PathBuf,
+    pub to: FileInfo,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ScanDelta {
+    pub added: Vec<FileInfo>,
+    pub modified: Vec<FileInfo>,
+    pub removed: Vec<PathBuf>,
+    pub renamed: Vec<RenamedFile>,
+    pub errors: Vec<ScanError>,
+}
+
+// The new state is only written as pending. Call `commit_scan_state` once the delta has been
+// applied, until then every scan reports its changes against the last committed state again.
+pub fn scan_for_changes(dir: &str, index_dir: &str, options: &ScanOptions) -> Result<ScanDelta, AppError> {
+    let root = Path::new(dir);
+    let index_dir = Path::new(index_dir);
+    let previous = ScanState::load(index_dir, root);
+
+    let mut current = ScanState {
+        root: root.to_path_buf(),
+        entries: HashMap::with_capacity(previous.entries.len()),
+        removed: HashMap::new(),
+    };
+    let mut delta = ScanDelta::default();
+    let mut added = Vec::new();
+    let mut no_longer_media = Vec::new();
+
+    for entry in StorehouseWalker::new(root, options)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                delta.errors.push(e);
+                continue;
+            }
+        };
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                delta.errors.push(ScanError::from_walk(&e, path));
+                continue;
+            }
+        };
+
+        let mut state = ScanStateEntry::from_metadata(&metadata);
+        match previous.entries.get(path) {
+            Some(old) if old.is_unchanged(&state) => {
+                state.file_type = old.file_type;
+                state.sample_hash = old.sample_hash.clone();
+            }
+            old => {
+                let info = match file_info_of(path) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        delta.errors.push(ScanError::from_io(path, &e));
+                        continue;
+                    }
+                };
+                state.file_type = info.as_ref().map(|info| info.file_type);
+                if info.is_some() {
+                    state.sample_hash = sample_hash_file_contents(path, SAMPLE_HASH_ALGORITHM).ok();
+                }
+                // The sampled hash misses edits between its chunks, so it only pairs up renames
+                // and never hides a change of size or modification time
+                match (info, old) {
+                    (Some(info), Some(old)) if old.file_type.is_some() => delta.modified.push(info),
+                    (Some(info), _) => added.push((info, state.clone())),
+                    // Overwritten with contents that are not media, gone as far as the index is concerned
+                    (None, Some(old)) if old.file_type.is_some() => no_longer_media.push(path.to_path_buf()),
+                    (None, _) => {}
+                }
+            }
+        }
+        current.entries.insert(path.to_path_buf(), state);
+    }
+
+    // Files that could not be read this time, or lie below a directory that could not be,
+    // are kept rather than reported as removed
+    let unreadable: HashSet<&Path> = delta.errors.iter().map(|error| error.path.as_path()).collect();
+    if !unreadable.is_empty() {
+        for (path, entry) in &previous.entries {
+            if !current.entries.contains_key(path) && path.ancestors().any(|ancestor| unreadable.contains(ancestor)) {
+                current.entries.insert(path.clone(), entry.clone());
+            }
+        }
+    }
+
+    let removed: Vec<(&PathBuf, &ScanStateEntry)> = previous
+        .entries
+        .iter()
+        .filter(|(path, old)| old.file_type.is_some() && !current.entries.contains_key(*path))
+        .collect();
+
+    // Pair up removed and added files that are the same file under a new path.
+    // Prefer the file id, then the sampled hash, and only for entries cached without
+    // a sampled hash fall back to size and modification time, which moves preserve.
+    let mut by_file_id: HashMap<FileId, (&PathBuf, &ScanStateEntry)> = HashMap::new();
+    let mut by_sample_hash: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+    let mut by_size_and_time: HashMap<(u64, u64), Vec<&PathBuf>> = HashMap::new();
+    for (path, old) in &removed {
+        if let Some(id) = old.file_id {
+            by_file_id.insert(id, (*path, *old));
+        }
+        match &old.sample_hash {
+            Some(sample_hash) => by_sample_hash.entry(sample_hash).or_default().push(*path),
+            None => by_size_and_time
+                .entry((old.size, old.modified_ns))
+                .or_default()
+                .push(*path),
+        }
+    }
+
+    let mut matched: HashSet<&PathBuf> = HashSet::new();
+    for (info, state) in added {
+        let by_id = state
+            .file_id
+            .and_then(|id| by_file_id.get(&id))
+            .filter(|(path, old)| {
+                // A reused inode is only the same file if the contents still match
+                old.size == state.size
+                    && (old.sample_hash.is_none() || old.sample_hash == state.sample_hash)
+                    && !matched.contains(*path)
+            })
+            .map(|(path, _)| *path);
+        let from = by_id
+            .or_else(|| {
+                let sample_hash = state.sample_hash.as_deref()?;
+                take_unmatched(by_sample_hash.get_mut(sample_hash), &matched)
+            })
+            .or_else(|| take_unmatched(by_size_and_time.get_mut(&(state.size, state.modified_ns)), &matched));
+
+        match from {
+            Some(from) => {
+                matched.insert(from);
+                delta.renamed.push(RenamedFile {
+                    from: from.clone(),
+                    to: info,
+                });
+            }
+            None => delta.added.push(info),
+        }
+    }
+    delta.removed = removed
+        .into_iter()
+        .filter(|(path, _)| !matched.contains(*path))
+        .map(|(path, _)| path.clone())
+        .chain(no_longer_media)
+        .collect();
+
+    current.removed = previous
+        .removed
+        .iter()
+        .chain(delta.removed.iter().map(|path| (path, &previous.entries[path])))
+        .filter(|(path, _)| !current.entries.contains_key(*path))
+        .map(|(path, entry)| (path.clone(), entry.clone()))
+        .collect();
+
+    current.write_to(index_dir, &ScanState::pending_path(index_dir))?;
+    Ok(delta)
+}
+
+// Records the state of the last scan of `dir` as seen, so its changes are not reported again
+pub fn commit_scan_state(dir: &str, index_dir: &str) -> Result<(), AppError> {
+    let index_dir = Path::new(index_dir);
+    let pending_path = ScanState::pending_path(index_dir);
+    match ScanState::read_from(&pending_path) {
+        Some(state) if state.root == Path::new(dir) => {
+            fs::rename(&pending_path, ScanState::path(index_dir)).map_err(|e| AppError::io(&pending_path, e))
+        }
+        Some(_) => Err(AppError::invalid_input(format!("The pending scan state does not belong to {}", dir))),
+        None => Err(AppError::NotFound {
+            path: Some(pending_path),
+            message: "No scan is waiting to be committed".to_string(),
+        }),
+    }
+}
+
+fn take_unmatched<'a>(paths: Option<&mut Vec<&'a PathBuf>>, matched: &HashSet<&PathBuf>) -> Option<&'a PathBuf> {
+    let paths = paths?;
+    while let Some(path) = paths.pop() {
+        if !matched.contains(path) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+// Drops paths from the cache so the next scan reports them as added again
+pub fn forget_scanned_files(dir: &str, index_dir: &str, paths: Vec<String>) -> Result<(), AppError> {
+    let index_dir = Path::new(index_dir);
+    let mut state = ScanState::load(index_dir, Path::new(dir));
+    for path in paths {
+        state.entries.remove(Path::new(&path));
+        state.removed.remove(Path::new(&path));
+    }
+    state.save(index_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A storehouse and an index directory that are removed again when the test ends
+    struct Storehouse {
+        root: PathBuf,
+    }
+
+    impl Storehouse {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("tag-anywhere-scan-state-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("files/sub")).unwrap();
+            Storehouse { root }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.root.join("files").join(name)
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            fs::write(self.path(name), contents).unwrap();
+        }
+
+        fn dirs(&self) -> (String, String) {
+            let dir = self.root.join("files");
+            let index_dir = self.root.join("index");
+            (dir.to_string_lossy().to_string(), index_dir.to_string_lossy().to_string())
+        }
+
+        fn scan_uncommitted(&self) -> ScanDelta {
+            let (dir, index_dir) = self.dirs();
+            scan_for_changes(&dir, &index_dir, &ScanOptions::default()).unwrap()
+        }
+
+        fn scan(&self) -> ScanDelta {
+            let delta = self.scan_uncommitted();
+            let (dir, index_dir) = self.dirs();
+            commit_scan_state(&dir, &index_dir).unwrap();
+            delta
+        }
+    }
+
+    impl Drop for Storehouse {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn paths(files: &[FileInfo]) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn first_scan_adds_media_files_only() {
+        let storehouse = Storehouse::new("first");
+        storehouse.write("a.jpg", "image a");
+        storehouse.write("sub/b.mp4", "video b");
+        storehouse.write("notes.txt", "not media");
+
+        let delta = storehouse.scan();
+        assert_eq!(paths(&delta.added), vec![storehouse.path("a.jpg"), storehouse.path("sub/b.mp4")]);
+        assert!(delta.modified.is_empty() && delta.removed.is_empty() && delta.renamed.is_empty());
+
+        let delta = storehouse.scan();
+        assert!(delta.added.is_empty() && delta.modified.is_empty() && delta.removed.is_empty() && delta.renamed.is_empty());
+    }
+
+    #[test]
+    fn reports_changes_again_until_the_scan_is_committed() {
+        let storehouse = Storehouse::new("commit");
+        storehouse.write("a.jpg", "image a");
+
+        assert_eq!(paths(&storehouse.scan_uncommitted().added), vec![storehouse.path("a.jpg")]);
+        assert_eq!(paths(&storehouse.scan().added), vec![storehouse.path("a.jpg")]);
+        assert!(storehouse.scan().added.is_empty());
+
+        let (dir, index_dir) = storehouse.dirs();
+        assert!(matches!(commit_scan_state(&dir, &index_dir), Err(AppError::NotFound { .. })));
+    }
+
+    #[test]
+    fn classifies_added_modified_and_removed_files() {
+        let storehouse = Storehouse::new("changes");
+        storehouse.write("a.jpg", "image a");
+        storehouse.write("b.jpg", "image b");
+        storehouse.write("c.jpg", "image c");
+        storehouse.scan();
+
+        storehouse.write("a.jpg", "image a, edited");
+        fs::remove_file(storehouse.path("c.jpg")).unwrap();
+        storehouse.write("d.jpg", "image d");
+
+        let delta = storehouse.scan();
+        assert_eq!(paths(&delta.added), vec![storehouse.path("d.jpg")]);
+        assert_eq!(paths(&delta.modified), vec![storehouse.path("a.jpg")]);
+        assert_eq!(delta.removed, vec![storehouse.path("c.jpg")]);
+        assert!(delta.renamed.is_empty());
+    }
+
+    #[test]
+    fn reports_edits_the_sampled_hash_misses_as_modified() {
+        let storehouse = Storehouse::new("sampled-edit");
+        let mut contents = vec![0u8; 1024 * 1024];
+        fs::write(storehouse.path("a.mp4"), &contents).unwrap();
+        storehouse.scan();
+
+        // Between the head and middle chunks, same size
+        contents[200 * 1024] = 1;
+        fs::write(storehouse.path("a.mp4"), &contents).unwrap();
+        let file = File::options().write(true).open(storehouse.path("a.mp4")).unwrap();
+        file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1)).unwrap();
+
+        let delta = storehouse.scan();
+        assert_eq!(paths(&delta.modified), vec![storehouse.path("a.mp4")]);
+    }
+
+    #[test]
+    fn pairs_moved_and_copied_files_as_renames() {
+        let storehouse = Storehouse::new("renames");
+        storehouse.write("moved.jpg", "moved image");
+        storehouse.write("copied.jpg", "copied image");
+        storehouse.scan();
+
+        // A move keeps the file id, a copy and delete only the contents
+        fs::rename(storehouse.path("moved.jpg"), storehouse.path("sub/moved.jpg")).unwrap();
+        fs::copy(storehouse.path("copied.jpg"), storehouse.path("sub/copied.jpg")).unwrap();
+        fs::remove_file(storehouse.path("copied.jpg")).unwrap();
+
+        let delta = storehouse.scan();
+        let mut renamed: Vec<(PathBuf, PathBuf)> = delta
+            .renamed
+            .iter()
+            .map(|renamed| (renamed.from.clone(), renamed.to.path.clone()))
+            .collect();
+        renamed.sort();
+        assert_eq!(
+            renamed,
+            vec![
+                (storehouse.path("copied.jpg"), storehouse.path("sub/copied.jpg")),
+                (storehouse.path("moved.jpg"), storehouse.path("sub/moved.jpg")),
+            ]
+        );
+        assert!(delta.added.is_empty() && delta.removed.is_empty());
+    }
+
+    #[test]
+    fn reports_files_that_are_no_longer_media_as_removed() {
+        let storehouse = Storehouse::new("type-change");
+        storehouse.write("a.png", "image a");
+        storehouse.scan();
+
+        // Sniffed contents win over the extension
+        storehouse.write("a.png", "%PDF-1.4 not an image any more");
+
+        let delta = storehouse.scan();
+        assert_eq!(delta.removed, vec![storehouse.path("a.png")]);
+        assert!(delta.added.is_empty() && delta.modified.is_empty());
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use mime_guess::mime;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_hash::{hash_file_contents, sample_hash_file_contents};
+use crate::file_scan::detect_mime;
+use crate::file_thumbnail::{create_thumbnail_set, CoverSelection, ThumbnailOptions, ThumbnailProfile, ThumbnailSet};
+use crate::scan_state::{ScanStateEntry, SAMPLE_HASH_ALGORITHM};
+
+const THUMBNAIL_CACHE_DIR_NAME: &str = "thumbnails";
+// One record per source path, named after the hash of the path
+const SOURCES_DIR_NAME: &str = "sources";
+// One record per content id, naming the file the thumbnails were made from
+const CONTENTS_DIR_NAME: &str = "contents";
+const LOCK_COUNT: usize = 16;
+
+// Thumbnails of the same contents are written by one thread at a time, others wait and reuse them.
+// Always taken before SOURCE_LOCKS.
+static CONTENT_LOCKS: [Mutex<()>; LOCK_COUNT] = [const { Mutex::new(()) }; LOCK_COUNT];
+// Guards writing the record of a source path
+static SOURCE_LOCKS: [Mutex<()>; LOCK_COUNT] = [const { Mutex::new(()) }; LOCK_COUNT];
+
+fn lock(mutex: &Mutex<()>) -> MutexGuard<'_, ()> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock_index(name: &str) -> usize {
+    name.bytes().fold(0usize, |sum, byte| sum.wrapping_mul(31).wrapping_add(byte as usize)) % LOCK_COUNT
+}
+
+fn content_lock(content_id: &str) -> &'static Mutex<()> {
+    &CONTENT_LOCKS[lock_index(content_id)]
+}
+
+fn source_lock(record_name: &str) -> &'static Mutex<()> {
+    &SOURCE_LOCKS[lock_index(record_name)]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedSource {
+    pub path: PathBuf,
+    pub state: ScanStateEntry,
+    pub content_id: String,
+    pub key: String,
+    // File names inside the cache directory
+    pub files: BTreeSet<String>,
+}
+
+// Sampled hashes can collide, so the first file seen with a content id is remembered and its
+// full hash only computed once a different file claims the same id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedContents {
+    pub path: PathBuf,
+    pub state: ScanStateEntry,
+    pub content_hash: Option<String>,
+}
+
+fn thumbnail_cache_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(THUMBNAIL_CACHE_DIR_NAME)
+}
+
+fn source_record_name(path: &Path) -> String {
+    let mut hasher = SAMPLE_HASH_ALGORITHM.hasher();
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    format!("{}.json", hasher.finish())
+}
+
+fn source_record_path(dir: &Path, record_name: &str) -> PathBuf {
+    dir.join(SOURCES_DIR_NAME).join(record_name)
+}
+
+fn contents_record_path(dir: &Path, content_id: &str) -> PathBuf {
+    dir.join(CONTENTS_DIR_NAME).join(format!("{}.json", content_id))
+}
+
+// A missing or unreadable record only costs hashing the source again
+fn read_record<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    File::open(path)
+        .ok()
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+}
+
+fn write_record<T: Serialize>(path: &Path, record: &T) -> Result<(), AppError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| AppError::io(parent, e))?;
+    }
+    let temp_path = path.with_extension("json.tmp");
+    let writer = BufWriter::new(File::create(&temp_path).map_err(|e| AppError::io(&temp_path, e))?);
+    serde_json::to_writer(writer, record)?;
+    fs::rename(&temp_path, path).map_err(|e| AppError::io(path, e))?;
+    Ok(())
+}
+
+fn remove_file_if_present(path: &Path) -> Result<(), AppError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(AppError::io(path, e)),
+        _ => Ok(()),
+    }
+}
+
+// Thumbnails are keyed on the size and sampled hash, which scan-state.json records as well.
+// A full hash is only read when another file, or an edited version of the same one, claims the
+// same id. A real collision is keyed on the full hash instead.
+fn resolve_content_id(dir: &Path, source: &Path, state: &mut ScanStateEntry) -> Result<String, AppError> {
+    let sample_hash = sample_hash_file_contents(source, SAMPLE_HASH_ALGORITHM).map_err(|e| AppError::io(source, e))?;
+    let content_id = format!("{}-{}", state.size, sample_hash);
+    state.sample_hash = Some(sample_hash);
+
+    let _content_guard = lock(content_lock(&content_id));
+    let record_path = contents_record_path(dir, &content_id);
+    let claimed = || CachedContents {
+        path: source.to_path_buf(),
+        state: state.clone(),
+        content_hash: None,
+    };
+    let Some(mut record) = read_record::<CachedContents>(&record_path) else {
+        write_record(&record_path, &claimed())?;
+        return Ok(content_id);
+    };
+    if record.path == source && record.state.is_unchanged(state) {
+        return Ok(content_id);
+    }
+
+    let content_hash = hash_file_contents(source, SAMPLE_HASH_ALGORITHM).map_err(|e| AppError::io(source, e))?;
+    let known_hash = match record.content_hash.clone() {
+        Some(known_hash) => Some(known_hash),
+        None => {
+            let unchanged = fs::metadata(&record.path)
+                .map(|metadata| record.state.is_unchanged(&ScanStateEntry::from_metadata(&metadata)))
+                .unwrap_or(false);
+            if unchanged {
+                let known_hash = hash_file_contents(&record.path, SAMPLE_HASH_ALGORITHM).ok();
+                record.content_hash = known_hash.clone();
+                write_record(&record_path, &record)?;
+                known_hash
+            } else {
+                None
+            }
+        }
+    };
+
+    match known_hash {
+        Some(known_hash) if known_hash == content_hash => Ok(content_id),
+        Some(_) => Ok(format!("{}-{}", state.size, content_hash)),
+        None => {
+            // The file the thumbnails were made from is gone or edited, so they cannot be trusted
+            remove_thumbnails_of(dir, &content_id)?;
+            write_record(
+                &record_path,
+                &CachedContents {
+                    content_hash: Some(content_hash),
+                    ..claimed()
+                },
+            )?;
+            Ok(content_id)
+        }
+    }
+}
+
+fn remove_thumbnails_of(dir: &Path, content_id: &str) -> Result<(), AppError> {
+    let prefixes = [format!("{}_", content_id), format!("{}-", content_id)];
+    for entry in fs::read_dir(dir).map_err(|e| AppError::io(dir, e))? {
+        let entry = entry.map_err(|e| AppError::io(dir, e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())) && entry.path().is_file() {
+            remove_file_if_present(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+// Images are keyed by their contents alone, video covers also by the frame they show
+fn cache_key(content_id: &str, is_video: bool, frame_number: Option<usize>, time: Option<f64>, cover_selection: CoverSelection) -> String {
+    match (is_video, time, cover_selection) {
+        (false, _, _) => content_id.to_string(),
+        (true, Some(time), _) => format!("{}-t{}", content_id, time),
+        (true, None, CoverSelection::Smart) => format!("{}-smart", content_id),
+        (true, None, CoverSelection::Fixed) => format!("{}-f{}", content_id, frame_number.unwrap_or(0)),
+    }
+}
+
+fn thumbnail_file_name(key: &str, profile: ThumbnailProfile, options: &ThumbnailOptions) -> String {
+    format!("{}_{}.{}", key, profile.name(), options.format.extension())
+}
+
+// Same as `create_thumbnail_set`, but the thumbnails are stored under the contents of the source
+// in `<cache_dir>/thumbnails`. Identical files share them, and they are only generated again when
+// the source changes or a thumbnail file is missing.
+pub fn cached_thumbnail_set(
+    file_path: String,
+    cache_dir: String,
+    frame_number: Option<usize>,
+    time: Option<f64>,
+    options: ThumbnailOptions,
+) -> Result<Option<ThumbnailSet>, AppError> {
+    let source = Path::new(&file_path);
+    let dir = thumbnail_cache_dir(Path::new(&cache_dir));
+
+    let mime_type = detect_mime(source).map_err(|e| AppError::io(source, e))?.mime_type();
+    let is_video = mime_type.type_() == mime::VIDEO;
+    if !is_video && mime_type.type_() != mime::IMAGE {
+        return Ok(None);
+    }
+
+    let metadata = fs::metadata(source).map_err(|e| AppError::io(source, e))?;
+    if !metadata.is_file() {
+        return Err(AppError::invalid_path(source, "Not a file"));
+    }
+    let mut state = ScanStateEntry::from_metadata(&metadata);
+
+    let record_name = source_record_name(source);
+    let record_path = source_record_path(&dir, &record_name);
+    let previous = read_record::<CachedSource>(&record_path).filter(|previous| previous.path == source);
+    let content_id = match previous.as_ref().filter(|previous| previous.state.is_unchanged(&state)) {
+        Some(previous) => {
+            state = previous.state.clone();
+            previous.content_id.clone()
+        }
+        None => resolve_content_id(&dir, source, &mut state)?,
+    };
+    let key = cache_key(&content_id, is_video, frame_number, time, options.cover_selection);
+
+    let _content_guard = lock(content_lock(&content_id));
+    let profiles = if options.profiles.is_empty() {
+        ThumbnailProfile::ALL.to_vec()
+    } else {
+        options.profiles.clone()
+    };
+    let thumbnails: ThumbnailSet = profiles
+        .iter()
+        .map(|profile| {
+            let path = dir.join(thumbnail_file_name(&key, *profile, &options));
+            (*profile, path.to_string_lossy().to_string())
+        })
+        .collect();
+
+    let missing: Vec<ThumbnailProfile> = thumbnails
+        .iter()
+        .filter(|(_, path)| !Path::new(path).is_file())
+        .map(|(profile, _)| *profile)
+        .collect();
+    if !missing.is_empty() {
+        fs::create_dir_all(&dir).map_err(|e| AppError::io(&dir, e))?;
+        let options = ThumbnailOptions {
+            profiles: missing,
+            format: options.format,
+            cover_selection: options.cover_selection,
+        };
+        let created = create_thumbnail_set(key.clone(), file_path.clone(), dir.to_string_lossy().to_string(), frame_number, time, options)?;
+        if created.is_none() {
+            return Ok(None);
+        }
+    }
+
+    // Recorded while the content lock is held, so garbage collection never sees the thumbnails unreferenced
+    let mut files: BTreeSet<String> = thumbnails
+        .keys()
+        .map(|profile| thumbnail_file_name(&key, *profile, &options))
+        .collect();
+    if let Some(previous) = previous.as_ref().filter(|previous| previous.key == key) {
+        files.extend(previous.files.iter().cloned());
+    }
+    let record = CachedSource {
+        path: source.to_path_buf(),
+        state,
+        content_id,
+        key,
+        files,
+    };
+    if previous.as_ref() != Some(&record) {
+        let _source_guard = lock(source_lock(&record_name));
+        write_record(&record_path, &record)?;
+    }
+
+    Ok(Some(thumbnails))
+}
+
+// Carries the thumbnails of moved or relinked files over to their new paths without hashing them again
+pub fn move_cached_thumbnails(cache_dir: String, moves: Vec<(String, String)>) -> Result<(), AppError> {
+    let dir = thumbnail_cache_dir(Path::new(&cache_dir));
+    for (from, to) in moves {
+        let from_name = source_record_name(Path::new(&from));
+        let from_path = source_record_path(&dir, &from_name);
+        let Some(mut record) = read_record::<CachedSource>(&from_path).filter(|record| record.path == Path::new(&from)) else {
+            continue;
+        };
+
+        // The new record is written before the old one is removed, with garbage collection held off
+        let _content_guard = lock(content_lock(&record.content_id));
+        record.path = PathBuf::from(&to);
+        let to_name = source_record_name(&record.path);
+        {
+            let _source_guard = lock(source_lock(&to_name));
+            write_record(&source_record_path(&dir, &to_name), &record)?;
+        }
+        let _source_guard = lock(source_lock(&from_name));
+        remove_file_if_present(&from_path)?;
+    }
+    Ok(())
+}
+
+// Drops the references of files that were removed from the storehouse.
+// Their thumbnails are deleted by the next garbage collection once nothing else uses them.
+pub fn release_cached_thumbnails(cache_dir: String, paths: Vec<String>) -> Result<(), AppError> {
+    let dir = thumbnail_cache_dir(Path::new(&cache_dir));
+    for path in paths {
+        let name = source_record_name(Path::new(&path));
+        let _source_guard = lock(source_lock(&name));
+        remove_file_if_present(&source_record_path(&dir, &name))?;
+    }
+    Ok(())
+}
+
+fn read_records<T: DeserializeOwned>(dir: &Path) -> Result<Vec<(PathBuf, Option<T>)>, AppError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut records = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| AppError::io(dir, e))? {
+        let path = entry.map_err(|e| AppError::io(dir, e))?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            let record = read_record(&path);
+            records.push((path, record));
+        }
+    }
+    Ok(records)
+}
+
+// Deletes the thumbnails no source references any more, and files the cache does not know,
+// e.g. left behind by a crash. Returns the deleted files.
+pub fn collect_thumbnail_garbage(cache_dir: String) -> Result<Vec<PathBuf>, AppError> {
+    let dir = thumbnail_cache_dir(Path::new(&cache_dir));
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    // Waits for thumbnails being written, which are only recorded once they are complete
+    let _content_guards: Vec<MutexGuard<'_, ()>> = CONTENT_LOCKS.iter().map(lock).collect();
+    let mut deleted = Vec::new();
+    let mut kept = BTreeSet::new();
+    let mut content_ids = BTreeSet::new();
+    for (path, record) in read_records::<CachedSource>(&dir.join(SOURCES_DIR_NAME))? {
+        match record {
+            Some(record) => {
+                kept.extend(record.files);
+                content_ids.insert(record.content_id);
+            }
+            None => {
+                remove_file_if_present(&path)?;
+                deleted.push(path);
+            }
+        }
+    }
+
+    for (path, _) in read_records::<CachedContents>(&dir.join(CONTENTS_DIR_NAME))? {
+        let content_id = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        if !content_ids.contains(&content_id) {
+            remove_file_if_present(&path)?;
+            deleted.push(path);
+        }
+    }
+
+    for entry in fs::read_dir(&dir).map_err(|e| AppError::io(&dir, e))? {
+        let entry = entry.map_err(|e| AppError::io(&dir, e))?;
+        let path = entry.path();
+        if !path.is_file() || kept.contains(entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        fs::remove_file(&path).map_err(|e| AppError::io(&path, e))?;
+        deleted.push(path);
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    // Source images and a cache directory that are removed again when the test ends
+    struct Cache {
+        root: PathBuf,
+    }
+
+    impl Cache {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("tag-anywhere-thumbnail-cache-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("files")).unwrap();
+            Cache { root }
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.root.join("files").join(name).to_string_lossy().to_string()
+        }
+
+        fn cache_dir(&self) -> String {
+            self.root.join("cache").to_string_lossy().to_string()
+        }
+
+        fn write_image(&self, name: &str, color: [u8; 3]) {
+            RgbImage::from_pixel(8, 8, Rgb(color)).save(self.path(name)).unwrap();
+        }
+
+        fn thumbnail(&self, name: &str) -> PathBuf {
+            let options = ThumbnailOptions {
+                profiles: vec![ThumbnailProfile::Grid],
+                ..ThumbnailOptions::default()
+            };
+            let thumbnails = cached_thumbnail_set(self.path(name), self.cache_dir(), None, None, options).unwrap().unwrap();
+            PathBuf::from(&thumbnails[&ThumbnailProfile::Grid])
+        }
+
+        fn collect_garbage(&self) -> Vec<PathBuf> {
+            collect_thumbnail_garbage(self.cache_dir()).unwrap()
+        }
+    }
+
+    impl Drop for Cache {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn identical_sources_share_one_thumbnail() {
+        let cache = Cache::new("identical");
+        cache.write_image("a.png", [200, 40, 40]);
+        cache.write_image("b.png", [200, 40, 40]);
+        cache.write_image("c.png", [40, 40, 200]);
+
+        let a = cache.thumbnail("a.png");
+        assert!(a.is_file());
+        assert_eq!(cache.thumbnail("b.png"), a);
+        assert_ne!(cache.thumbnail("c.png"), a);
+        assert_eq!(cache.collect_garbage(), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn moved_sources_keep_their_thumbnail() {
+        let cache = Cache::new("moved");
+        cache.write_image("a.png", [200, 40, 40]);
+        let thumbnail = cache.thumbnail("a.png");
+
+        fs::rename(cache.path("a.png"), cache.path("moved.png")).unwrap();
+        move_cached_thumbnails(cache.cache_dir(), vec![(cache.path("a.png"), cache.path("moved.png"))]).unwrap();
+        release_cached_thumbnails(cache.cache_dir(), vec![cache.path("a.png")]).unwrap();
+
+        assert_eq!(cache.collect_garbage(), Vec::<PathBuf>::new());
+        assert!(thumbnail.is_file());
+        assert_eq!(cache.thumbnail("moved.png"), thumbnail);
+    }
+
+    #[test]
+    fn garbage_collection_deletes_only_unreferenced_thumbnails() {
+        let cache = Cache::new("garbage");
+        cache.write_image("a.png", [200, 40, 40]);
+        cache.write_image("b.png", [200, 40, 40]);
+        cache.write_image("c.png", [40, 40, 200]);
+        let shared = cache.thumbnail("a.png");
+        cache.thumbnail("b.png");
+        let single = cache.thumbnail("c.png");
+
+        release_cached_thumbnails(cache.cache_dir(), vec![cache.path("a.png")]).unwrap();
+        let deleted = cache.collect_garbage();
+        assert!(!deleted.contains(&shared), "{:?}", deleted);
+        assert!(shared.is_file());
+
+        release_cached_thumbnails(cache.cache_dir(), vec![cache.path("b.png"), cache.path("c.png")]).unwrap();
+        let deleted = cache.collect_garbage();
+        assert!(deleted.contains(&shared) && deleted.contains(&single), "{:?}", deleted);
+        assert!(!shared.exists() && !single.exists());
+    }
+}
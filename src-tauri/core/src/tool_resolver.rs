@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{OnceLock, RwLock};
+use serde::Deserialize;
+use crate::error::AppError;
+
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+// Tools can print a lot before failing, the end of stderr usually explains why
+const STDERR_TAIL_LENGTH: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+    Ffmpeg,
+    Ffprobe,
+    Magick,
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Ffmpeg => "ffmpeg",
+            Tool::Ffprobe => "ffprobe",
+            Tool::Magick => "magick",
+        }
+    }
+
+    fn env_var(&self) -> &'static str {
+        match self {
+            Tool::Ffmpeg => "TAG_ANYWHERE_FFMPEG",
+            Tool::Ffprobe => "TAG_ANYWHERE_FFPROBE",
+            Tool::Magick => "TAG_ANYWHERE_MAGICK",
+        }
+    }
+
+    // Names of older releases that are still packaged by some systems, e.g. ImageMagick 6 on
+    // Debian and Ubuntu has no `magick`, its `convert` takes the same arguments.
+    // Not on Windows, where `convert.exe` is the system tool converting FAT volumes to NTFS.
+    fn legacy_names(&self) -> &'static [&'static str] {
+        match self {
+            Tool::Magick if !cfg!(windows) => &["convert"],
+            _ => &[],
+        }
+    }
+
+    // Location of the binary inside the bundled `bin` resource folder
+    fn bundled_path(&self) -> &'static str {
+        if cfg!(windows) {
+            match self {
+                Tool::Ffmpeg => "bin/ffmpeg/ffmpeg-win.exe",
+                Tool::Ffprobe => "bin/ffmpeg/ffprobe-win.exe",
+                Tool::Magick => "bin/magick/magick.exe",
+            }
+        } else {
+            match self {
+                Tool::Ffmpeg => "bin/ffmpeg/ffmpeg",
+                Tool::Ffprobe => "bin/ffmpeg/ffprobe",
+                Tool::Magick => "bin/magick/magick",
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ToolResolver {
+    resource_dir: Option<PathBuf>,
+    configured: HashMap<Tool, PathBuf>,
+}
+
+impl ToolResolver {
+    pub fn new() -> Self {
+        ToolResolver::default()
+    }
+
+    pub fn set_resource_dir(&mut self, dir: PathBuf) {
+        self.resource_dir = Some(dir);
+    }
+
+    pub fn set_tool_path(&mut self, tool: Tool, path: Option<PathBuf>) {
+        match path {
+            Some(path) => self.configured.insert(tool, path),
+            None => self.configured.remove(&tool),
+        };
+    }
+
+    pub fn resolve(&self, tool: Tool) -> Result<PathBuf, AppError> {
+        // 1. Explicitly configured path, either from the settings or the environment
+        let configured = self
+            .configured
+            .get(&tool)
+            .cloned()
+            .or_else(|| env::var_os(tool.env_var()).map(PathBuf::from));
+        if let Some(path) = configured {
+            if path.is_file() {
+                return Ok(path);
+            }
+            return Err(AppError::ToolMissing {
+                tool: tool.name().to_string(),
+                message: format!("{} is configured at {} but no file exists there", tool.name(), path.display()),
+            });
+        }
+
+        let mut searched = Vec::new();
+
+        // 2. Binaries shipped with the app, then the development `bin` folder
+        let mut bundled_dirs = Vec::new();
+        if let Some(resource_dir) = &self.resource_dir {
+            bundled_dirs.push(resource_dir.clone());
+        }
+        if let Ok(current_dir) = env::current_dir() {
+            bundled_dirs.push(current_dir);
+        }
+        for dir in bundled_dirs {
+            let candidate = dir.join(tool.bundled_path());
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+            searched.push(candidate.display().to_string());
+        }
+
+        // 3. Anything installed on the system
+        for name in [tool.name()].iter().chain(tool.legacy_names()) {
+            if let Some(path) = find_in_path(name) {
+                return Ok(path);
+            }
+        }
+        searched.push("$PATH".to_string());
+
+        Err(AppError::ToolMissing {
+            tool: tool.name().to_string(),
+            message: format!(
+                "{} was not found. Install it or configure its location. Searched: {}",
+                tool.name(),
+                searched.join(", ")
+            ),
+        })
+    }
+
+    pub fn command(&self, tool: Tool) -> Result<ToolCommand, AppError> {
+        let mut command = Command::new(self.resolve(tool)?);
+        hide_console_window(&mut command);
+        Ok(ToolCommand { tool, command })
+    }
+}
+
+// A command for one of the external tools, which reports failures as `ToolMissing` or `ToolFailed`
+pub struct ToolCommand {
+    tool: Tool,
+    command: Command,
+}
+
+impl ToolCommand {
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.command.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command.args(args);
+        self
+    }
+
+    // Runs the tool without looking at its exit status, for tools that report through a failing exit
+    pub fn output(&mut self) -> Result<Output, AppError> {
+        self.command.output().map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => AppError::ToolMissing {
+                tool: self.tool.name().to_string(),
+                message: format!("{} could not be started: {}", self.tool.name(), e),
+            },
+            _ => AppError::from(e),
+        })
+    }
+
+    pub fn run(&mut self) -> Result<Output, AppError> {
+        let output = self.output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim();
+            let start = stderr
+                .char_indices()
+                .rev()
+                .nth(STDERR_TAIL_LENGTH - 1)
+                .map_or(0, |(index, _)| index);
+            return Err(AppError::ToolFailed {
+                tool: self.tool.name().to_string(),
+                exit_code: output.status.code(),
+                stderr: stderr[start..].to_string(),
+            });
+        }
+        Ok(output)
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(windows)]
+fn hide_console_window(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    command.creation_flags(CREATE_NO_WINDOW);
+}
+
+#[cfg(not(windows))]
+fn hide_console_window(_command: &mut Command) {}
+
+fn resolver() -> &'static RwLock<ToolResolver> {
+    static RESOLVER: OnceLock<RwLock<ToolResolver>> = OnceLock::new();
+    RESOLVER.get_or_init(|| RwLock::new(ToolResolver::new()))
+}
+
+pub fn set_resource_dir(dir: PathBuf) {
+    resolver()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .set_resource_dir(dir);
+}
+
+pub fn set_tool_path(tool: Tool, path: Option<PathBuf>) {
+    resolver()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .set_tool_path(tool, path);
+}
+
+pub fn resolve_tool(tool: Tool) -> Result<PathBuf, AppError> {
+    resolver().read().unwrap_or_else(|e| e.into_inner()).resolve(tool)
+}
+
+pub fn tool_command(tool: Tool) -> Result<ToolCommand, AppError> {
+    resolver().read().unwrap_or_else(|e| e.into_inner()).command(tool)
+}
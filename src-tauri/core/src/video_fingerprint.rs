@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use image::DynamicImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::file_scan::{file_type_of, FileType, ScanError, ScanOptions, StorehouseWalker};
+use crate::perceptual_hash::{cluster_within, hamming_distance, PerceptualHashKind};
+use crate::scan_state::ScanStateEntry;
+use crate::video_frame::{extract_gray_frame, get_duration};
+
+const FINGERPRINT_FILE_NAME: &str = "video-fingerprints.json";
+const FINGERPRINT_FRAMES: usize = 16;
+const FINGERPRINT_FRAME_SIZE: u32 = 64;
+const FINGERPRINT_HASH: PerceptualHashKind = PerceptualHashKind::PHash;
+// Average Hamming distance per frame
+pub const DEFAULT_VIDEO_SIMILARITY_THRESHOLD: u32 = 10;
+
+// Frames are taken at the same fractions of the duration in every video, so the
+// same content at another resolution or bitrate lines up frame by frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoFingerprint {
+    pub size: u64,
+    pub modified_ns: u64,
+    pub duration: f64,
+    pub frames: Vec<u64>,
+}
+
+impl VideoFingerprint {
+    fn is_current(&self, state: &ScanStateEntry) -> bool {
+        self.size == state.size && self.modified_ns == state.modified_ns && self.frames.len() == FINGERPRINT_FRAMES
+    }
+}
+
+// Sum of the per-frame distances, which keeps the triangle inequality the BK-tree relies on
+fn fingerprint_distance(a: &VideoFingerprint, b: &VideoFingerprint) -> u32 {
+    a.frames
+        .iter()
+        .zip(&b.frames)
+        .map(|(a, b)| hamming_distance(*a, *b))
+        .sum()
+}
+
+pub fn fingerprint_video(path: &Path) -> Result<VideoFingerprint, AppError> {
+    let state = ScanStateEntry::from_metadata(&fs::metadata(path)?);
+    let file_path = path.to_string_lossy();
+    let duration = get_duration(&file_path)?;
+
+    let mut frames = Vec::with_capacity(FINGERPRINT_FRAMES);
+    for index in 0..FINGERPRINT_FRAMES {
+        // Stay clear of the very first and last frame, which are often black
+        let time = duration * (index + 1) as f64 / (FINGERPRINT_FRAMES + 1) as f64;
+        let frame = extract_gray_frame(&file_path, time, FINGERPRINT_FRAME_SIZE, FINGERPRINT_FRAME_SIZE)?;
+        frames.push(FINGERPRINT_HASH.compute(&DynamicImage::ImageLuma8(frame)));
+    }
+
+    Ok(VideoFingerprint {
+        size: state.size,
+        modified_ns: state.modified_ns,
+        duration,
+        frames,
+    })
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VideoFingerprintStore {
+    pub root: PathBuf,
+    pub entries: HashMap<PathBuf, VideoFingerprint>,
+    // Files that are not videos, so they are not sniffed again until they change
+    #[serde(default)]
+    pub other_files: HashMap<PathBuf, ScanStateEntry>,
+}
+
+impl VideoFingerprintStore {
+    pub fn path(index_dir: &Path) -> PathBuf {
+        index_dir.join(FINGERPRINT_FILE_NAME)
+    }
+
+    pub fn load(index_dir: &Path, root: &Path) -> Self {
+        let store = File::open(Self::path(index_dir))
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, VideoFingerprintStore>(BufReader::new(file)).ok());
+
+        match store {
+            Some(store) if store.root == root => store,
+            _ => VideoFingerprintStore {
+                root: root.to_path_buf(),
+                entries: HashMap::new(),
+                other_files: HashMap::new(),
+            },
+        }
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<(), AppError> {
+        fs::create_dir_all(index_dir)?;
+        let path = Self::path(index_dir);
+        let temp_path = path.with_extension("json.tmp");
+
+        let writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(writer, self)?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarVideo {
+    pub path: PathBuf,
+    pub size: u64,
+    pub duration: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarVideoGroup {
+    // Largest file first, as that is usually the highest quality copy
+    pub videos: Vec<SimilarVideo>,
+    // Average Hamming distance per frame between the two least similar videos
+    pub max_distance: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SimilarVideosReport {
+    pub groups: Vec<SimilarVideoGroup>,
+    pub errors: Vec<ScanError>,
+}
+
+// Fingerprints are cached in the index directory and only recomputed for new or changed videos
+pub fn find_similar_video_files(
+  dir_path: String,
+  index_dir: String,
+  options: ScanOptions,
+  threshold: u32,
+) -> Result<SimilarVideosReport, AppError> {
+    let root = Path::new(&dir_path);
+    let index_dir = Path::new(&index_dir);
+    let mut report = SimilarVideosReport::default();
+    let previous = VideoFingerprintStore::load(index_dir, root);
+    let mut store = VideoFingerprintStore {
+        root: root.to_path_buf(),
+        entries: HashMap::new(),
+        other_files: HashMap::new(),
+    };
+
+    let mut cached = Vec::new();
+    let mut missing = Vec::new();
+    for entry in StorehouseWalker::new(root, &options)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+        let state = match entry.metadata() {
+            Ok(metadata) => ScanStateEntry::from_metadata(&metadata),
+            Err(e) => {
+                report.errors.push(ScanError::from_walk(&e, entry.path()));
+                continue;
+            }
+        };
+        if previous.other_files.get(entry.path()).is_some_and(|other| other.is_unchanged(&state)) {
+            store.other_files.insert(entry.into_path(), state);
+            continue;
+        }
+        match previous.entries.get(entry.path()) {
+            Some(fingerprint) if fingerprint.is_current(&state) => cached.push((entry.into_path(), fingerprint.clone())),
+            _ => missing.push((entry.into_path(), state)),
+        }
+    }
+
+    let computed: Vec<_> = missing
+        .into_par_iter()
+        .map(|(path, state)| {
+            let fingerprint = match file_type_of(&path) {
+                Ok(Some(FileType::Video)) => fingerprint_video(&path),
+                Ok(_) => return (path, state, None),
+                Err(e) => Err(AppError::io(&path, e)),
+            };
+            (path, state, Some(fingerprint))
+        })
+        .collect();
+
+    for (path, state, fingerprint) in computed {
+        match fingerprint {
+            Some(Ok(fingerprint)) => cached.push((path, fingerprint)),
+            Some(Err(e)) => report.errors.push(ScanError::from_app_error(&path, &e)),
+            None => {
+                store.other_files.insert(path, state);
+            }
+        }
+    }
+    for (path, fingerprint) in &cached {
+        store.entries.insert(path.clone(), fingerprint.clone());
+    }
+    store.save(index_dir)?;
+
+    let fingerprints: Vec<&VideoFingerprint> = cached.iter().map(|(_, fingerprint)| fingerprint).collect();
+    let threshold = threshold.saturating_mul(FINGERPRINT_FRAMES as u32);
+    for cluster in cluster_within(&fingerprints, |a, b| fingerprint_distance(a, b), threshold) {
+        let mut max_distance = 0;
+        for (i, a) in cluster.iter().enumerate() {
+            for b in &cluster[i + 1..] {
+                max_distance = max_distance.max(fingerprint_distance(fingerprints[*a], fingerprints[*b]));
+            }
+        }
+
+        let mut videos: Vec<SimilarVideo> = cluster
+            .into_iter()
+            .map(|index| {
+                let (path, fingerprint) = &cached[index];
+                SimilarVideo {
+                    path: path.clone(),
+                    size: fingerprint.size,
+                    duration: fingerprint.duration,
+                }
+            })
+            .collect();
+        videos.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        report.groups.push(SimilarVideoGroup {
+            videos,
+            max_distance: max_distance / FINGERPRINT_FRAMES as u32,
+        });
+    }
+    report.groups.sort_by(|a, b| a.videos[0].path.cmp(&b.videos[0].path));
+
+    Ok(report)
+}
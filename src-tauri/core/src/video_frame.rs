@@ -0,0 +1,145 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use image::{DynamicImage, GrayImage, ImageFormat};
+use crate::error::AppError;
+use crate::tool_resolver::{tool_command, Tool};
+
+fn invalid_output(file_path: &str, error: impl Display) -> AppError {
+    AppError::Decode {
+        path: Some(PathBuf::from(file_path)),
+        message: format!("Unexpected FFprobe output: {}", error),
+    }
+}
+
+pub fn get_frame_rate(file_path: &str) -> Result<f64, AppError> {
+    let ffprobe_output = tool_command(Tool::Ffprobe)?
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=r_frame_rate",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+            file_path,
+        ])
+        .run()?;
+
+    let output = String::from_utf8_lossy(&ffprobe_output.stdout);
+    let frame_rate_str = output.trim();
+
+    if frame_rate_str.is_empty() {
+        return Ok(-1.0);
+    }
+
+    let parts: Vec<&str> = frame_rate_str.split('/').collect();
+    if parts.len() == 2 {
+        let numerator = parts[0].parse::<f64>().map_err(|e| invalid_output(file_path, e))?;
+        let denominator = parts[1].parse::<f64>().map_err(|e| invalid_output(file_path, e))?;
+        return Ok(numerator / denominator);
+    }
+
+    Ok(-1.0)
+}
+
+pub fn get_duration(file_path: &str) -> Result<f64, AppError> {
+    let ffprobe_output = tool_command(Tool::Ffprobe)?
+        .args([
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+            file_path,
+        ])
+        .run()?;
+
+    String::from_utf8_lossy(&ffprobe_output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| AppError::Decode {
+            path: Some(PathBuf::from(file_path)),
+            message: "FFprobe did not report a duration".to_string(),
+        })
+}
+
+// Size of the first video stream as FFmpeg decodes it, i.e. with rotated streams turned upright
+pub fn get_dimensions(file_path: &str) -> Result<(u32, u32), AppError> {
+    let ffprobe_output = tool_command(Tool::Ffprobe)?
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=width,height:stream_side_data=rotation",
+            "-of", "default=noprint_wrappers=1",
+            file_path,
+        ])
+        .run()?;
+
+    let output = String::from_utf8_lossy(&ffprobe_output.stdout);
+    let (mut width, mut height, mut rotation) = (None, None, 0.0);
+    for line in output.lines() {
+        match line.trim().split_once('=') {
+            Some(("width", value)) => width = value.parse::<u32>().ok(),
+            Some(("height", value)) => height = value.parse::<u32>().ok(),
+            Some(("rotation", value)) => rotation = value.parse::<f64>().unwrap_or(0.0),
+            _ => {}
+        }
+    }
+
+    match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => {
+            if (rotation.abs() % 180.0 - 90.0).abs() < 1.0 {
+                Ok((height, width))
+            } else {
+                Ok((width, height))
+            }
+        }
+        _ => Err(AppError::Decode {
+            path: Some(PathBuf::from(file_path)),
+            message: "FFprobe did not report the video size".to_string(),
+        }),
+    }
+}
+
+// Decodes a single frame scaled to `width`x`height` and reads it from FFmpeg's stdout,
+// so no temporary image has to be written
+pub fn extract_gray_frame(file_path: &str, time: f64, width: u32, height: u32) -> Result<GrayImage, AppError> {
+    let ffmpeg_output = tool_command(Tool::Ffmpeg)?
+        .args([
+            "-v", "error",
+            "-ss", &time.to_string(),
+            "-i", file_path,
+            "-frames:v", "1",
+            "-vf", &format!("scale={}:{}:flags=area", width, height),
+            "-pix_fmt", "gray",
+            "-f", "rawvideo",
+            "-",
+        ])
+        .run()?;
+
+    GrayImage::from_raw(width, height, ffmpeg_output.stdout)
+        .ok_or_else(|| AppError::Decode {
+            path: Some(PathBuf::from(file_path)),
+            message: format!("FFmpeg returned no frame at {}s", time),
+        })
+}
+
+// Decodes a single frame scaled to `width`, keeping the aspect ratio, from a PNG on FFmpeg's stdout
+pub fn extract_frame(file_path: &str, time: f64, width: u32) -> Result<DynamicImage, AppError> {
+    let ffmpeg_output = tool_command(Tool::Ffmpeg)?
+        .args([
+            "-v", "error",
+            "-ss", &time.to_string(),
+            "-i", file_path,
+            "-frames:v", "1",
+            "-vf", &format!("scale={}:-2", width),
+            "-c:v", "png",
+            "-f", "image2pipe",
+            "-",
+        ])
+        .run()?;
+
+    if ffmpeg_output.stdout.is_empty() {
+        return Err(AppError::Decode {
+            path: Some(PathBuf::from(file_path)),
+            message: format!("FFmpeg returned no frame at {}s", time),
+        });
+    }
+    image::load_from_memory_with_format(&ffmpeg_output.stdout, ImageFormat::Png)
+        .map_err(|e| AppError::from(e).with_path(Path::new(file_path)))
+}
@@ -1,7 +0,0 @@
-pub mod tauri_file_scan;
-pub mod tauri_file_hash;
-pub mod tauri_file_thumbnail;
-pub mod tauri_file_operation;
-pub mod tauri_video_frame;
-pub mod tauri_file_stats;
-pub mod error;
\ No newline at end of file
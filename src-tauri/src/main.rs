@@ -1,27 +1,157 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use app::{error::AppError, tauri_file_hash::{read_files_and_hash, read_single_file_and_hash, FileHashResult}, tauri_file_operation::{copy_file_to_directory, delete_unlisted_files}, tauri_file_scan::{get_files_with_types, get_files_with_types_from_paths, FileInfo}, tauri_file_stats::{get_stats, FileStats}, tauri_file_thumbnail::create_thumbnail, tauri_video_frame::get_frame_rate};
-use tauri::command;
+use tag_anywhere_core::{error::AppError, file_duplicates::{find_duplicate_files, DuplicateReport}, file_relink::{relink_missing_files, MissingFile, RelinkReport}, file_manifest::{verify_manifest, write_manifest, ManifestVerifyReport, ManifestWriteReport}, file_hash::{read_files_and_hash, read_single_file_and_hash, FileHashResult, HashAlgorithm, HashBatchReport, HashMode}, file_operation::{copy_file_to_directory, delete_unlisted_files}, file_scan::{get_files_with_types, get_files_with_types_from_paths, ScanOptions, ScanReport}, file_stats::{get_stats, FileStats}, file_thumbnail::{create_thumbnail, create_thumbnail_set, create_thumbnail_with_preview, create_video_storyboard, CoverSelection, PreviewOptions, Storyboard, StoryboardOptions, ThumbnailOptions, ThumbnailSet, ThumbnailWithPreview}, file_watcher::{watch_storehouse, StorehouseWatcher}, job::{JobId, JobManager, JobRequest, JobStatus}, perceptual_hash::{find_similar_image_files, read_single_image_and_hash, PerceptualHashKind, PerceptualHashResult, SimilarImagesReport, DEFAULT_SIMILARITY_THRESHOLD}, scan_state::{commit_scan_state, forget_scanned_files, scan_for_changes, ScanDelta}, thumbnail_cache::{cached_thumbnail_set, collect_thumbnail_garbage, move_cached_thumbnails, release_cached_thumbnails}, tool_resolver::{resolve_tool, set_resource_dir, set_tool_path, Tool}, video_fingerprint::{find_similar_video_files, SimilarVideosReport, DEFAULT_VIDEO_SIMILARITY_THRESHOLD}, video_frame::get_frame_rate};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{command, State, Window};
+
+struct WatcherState(Mutex<Option<StorehouseWatcher>>);
+
+// Sync commands run on the main thread, so blocking work of async commands is moved off the async runtime instead
+async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> Result<T, AppError> + Send + 'static) -> Result<T, AppError> {
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| AppError::internal(e.to_string()))?
+}
+
+#[command]
+async fn hash_file(file_path: String, algorithm: Option<HashAlgorithm>, mode: Option<HashMode>) -> Result<FileHashResult, AppError> {
+    run_blocking(move || read_single_file_and_hash(file_path, algorithm.unwrap_or_default(), mode.unwrap_or_default())).await
+}
+
+#[command]
+async fn hash_files_in_directory(dir_path: String, skip_paths: Vec<String>, options: Option<ScanOptions>, algorithm: Option<HashAlgorithm>, mode: Option<HashMode>) -> Result<HashBatchReport, AppError> {
+    run_blocking(move || read_files_and_hash(dir_path, skip_paths, options.unwrap_or_default(), algorithm.unwrap_or_default(), mode.unwrap_or_default())).await
+}
+
+#[command]
+async fn find_duplicates(dir_path: String, options: Option<ScanOptions>, algorithm: Option<HashAlgorithm>) -> Result<DuplicateReport, AppError> {
+    run_blocking(move || find_duplicate_files(dir_path, options.unwrap_or_default(), algorithm.unwrap_or_default())).await
+}
+
+#[command]
+async fn perceptual_hash_image(file_path: String, kind: Option<PerceptualHashKind>) -> Result<PerceptualHashResult, AppError> {
+    run_blocking(move || read_single_image_and_hash(file_path, kind.unwrap_or_default())).await
+}
+
+#[command]
+async fn find_similar_images(dir_path: String, options: Option<ScanOptions>, kind: Option<PerceptualHashKind>, threshold: Option<u32>) -> Result<SimilarImagesReport, AppError> {
+    run_blocking(move || find_similar_image_files(dir_path, options.unwrap_or_default(), kind.unwrap_or_default(), threshold.unwrap_or(DEFAULT_SIMILARITY_THRESHOLD))).await
+}
+
+#[command]
+async fn find_similar_videos(dir_path: String, index_dir: String, options: Option<ScanOptions>, threshold: Option<u32>) -> Result<SimilarVideosReport, AppError> {
+    run_blocking(move || find_similar_video_files(dir_path, index_dir, options.unwrap_or_default(), threshold.unwrap_or(DEFAULT_VIDEO_SIMILARITY_THRESHOLD))).await
+}
+
+#[command]
+async fn write_checksum_manifest(manifest_path: String, paths: Vec<String>, options: Option<ScanOptions>, algorithm: Option<HashAlgorithm>) -> Result<ManifestWriteReport, AppError> {
+    run_blocking(move || write_manifest(manifest_path, paths, options.unwrap_or_default(), algorithm)).await
+}
+
+#[command]
+async fn verify_checksum_manifest(manifest_path: String, algorithm: Option<HashAlgorithm>) -> Result<ManifestVerifyReport, AppError> {
+    run_blocking(move || verify_manifest(manifest_path, algorithm)).await
+}
+
+#[command]
+async fn relink_missing(missing: Vec<MissingFile>, search_root: String, index_dir: Option<String>, skip_paths: Vec<String>, options: Option<ScanOptions>) -> Result<RelinkReport, AppError> {
+    run_blocking(move || relink_missing_files(missing, search_root, index_dir, skip_paths, options.unwrap_or_default())).await
+}
+
+#[command]
+fn fetch_files_with_types(dir: String, options: Option<ScanOptions>) -> Result<ScanReport, AppError> {
+    get_files_with_types(&dir, &options.unwrap_or_default())
+}
+
+#[command]
+async fn fetch_scan_delta(dir: String, index_dir: String, options: Option<ScanOptions>) -> Result<ScanDelta, AppError> {
+    run_blocking(move || scan_for_changes(&dir, &index_dir, &options.unwrap_or_default())).await
+}
+
+#[command]
+async fn commit_scan(dir: String, index_dir: String) -> Result<(), AppError> {
+    run_blocking(move || commit_scan_state(&dir, &index_dir)).await
+}
+
+#[command]
+fn forget_scanned_file_paths(dir: String, index_dir: String, paths: Vec<String>) -> Result<(), AppError> {
+    forget_scanned_files(&dir, &index_dir, paths)
+}
+
+#[command]
+async fn start_storehouse_watcher(window: Window, state: State<'_, WatcherState>, dir: String, options: Option<ScanOptions>) -> Result<(), AppError> {
+    let watcher = run_blocking(move || {
+        watch_storehouse(&dir, &options.unwrap_or_default(), move |event| {
+            let _ = window.emit(event.name(), &event);
+        })
+    })
+    .await?;
+    *state.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(watcher);
+    Ok(())
+}
+
+#[command]
+fn stop_storehouse_watcher(state: State<'_, WatcherState>) {
+    *state.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+#[command]
+fn submit_job(window: Window, jobs: State<'_, JobManager>, request: JobRequest) -> JobId {
+    jobs.submit(request, move |progress| {
+        let _ = window.emit("job-progress", progress);
+    })
+}
+
+#[command]
+fn cancel_job(jobs: State<'_, JobManager>, id: JobId) -> Result<(), AppError> {
+    jobs.cancel(id)
+}
+
+#[command]
+fn get_job_status(jobs: State<'_, JobManager>, id: JobId) -> Result<JobStatus, AppError> {
+    jobs.status(id)
+}
 
 #[command]
-async fn hash_file(file_path: String) -> Result<FileHashResult, AppError> {
-    read_single_file_and_hash(file_path).await
+fn create_thumbnail_for_file(cover_name: String, file_path: String, index_dir: String, frame_number: Option<usize>, time: Option<f64>, cover_selection: Option<CoverSelection>) -> Result<Option<String>, AppError> {
+    create_thumbnail(cover_name, file_path, index_dir, frame_number, time, cover_selection.unwrap_or_default())
 }
 
 #[command]
-async fn hash_files_in_directory(dir_path: String, skip_paths: Vec<String>) -> Result<Vec<FileHashResult>, AppError> {
-    read_files_and_hash(dir_path, skip_paths).await
+async fn create_thumbnail_with_preview_for_file(cover_name: String, file_path: String, index_dir: String, frame_number: Option<usize>, time: Option<f64>, cover_selection: Option<CoverSelection>, preview: Option<PreviewOptions>) -> Result<ThumbnailWithPreview, AppError> {
+    run_blocking(move || create_thumbnail_with_preview(cover_name, file_path, index_dir, frame_number, time, cover_selection.unwrap_or_default(), preview.unwrap_or_default())).await
 }
 
 #[command]
-fn fetch_files_with_types(dir: String) -> Result<Vec<FileInfo>, AppError> {
-    get_files_with_types(&dir)
+async fn create_storyboard(name: String, file_path: String, index_dir: String, options: Option<StoryboardOptions>) -> Result<Option<Storyboard>, AppError> {
+    run_blocking(move || create_video_storyboard(name, file_path, index_dir, options.unwrap_or_default())).await
 }
 
 #[command]
-fn create_thumbnail_for_file(cover_name: String, file_path: String, index_dir: String, frame_number: Option<usize>, time: Option<f64>) -> Result<Option<String>, AppError> {
-    create_thumbnail(cover_name, file_path, index_dir, frame_number, time)
+async fn create_thumbnail_set_for_file(cover_name: String, file_path: String, index_dir: String, frame_number: Option<usize>, time: Option<f64>, options: Option<ThumbnailOptions>) -> Result<Option<ThumbnailSet>, AppError> {
+    run_blocking(move || create_thumbnail_set(cover_name, file_path, index_dir, frame_number, time, options.unwrap_or_default())).await
+}
+
+#[command]
+async fn get_cached_thumbnail_set(file_path: String, cache_dir: String, frame_number: Option<usize>, time: Option<f64>, options: Option<ThumbnailOptions>) -> Result<Option<ThumbnailSet>, AppError> {
+    run_blocking(move || cached_thumbnail_set(file_path, cache_dir, frame_number, time, options.unwrap_or_default())).await
+}
+
+#[command]
+fn move_thumbnails(cache_dir: String, moves: Vec<(String, String)>) -> Result<(), AppError> {
+    move_cached_thumbnails(cache_dir, moves)
+}
+
+#[command]
+async fn release_thumbnails(cache_dir: String, paths: Vec<String>) -> Result<(), AppError> {
+    run_blocking(move || release_cached_thumbnails(cache_dir, paths)).await
+}
+
+#[command]
+async fn collect_thumbnail_cache_garbage(cache_dir: String) -> Result<Vec<PathBuf>, AppError> {
+    run_blocking(move || collect_thumbnail_garbage(cache_dir)).await
 }
 
 #[command]
@@ -30,7 +160,7 @@ fn copy_file_to_dir(file_path: String, directory_path: String) -> Result<String,
 }
 
 #[command]
-fn fetch_files_with_types_from_paths(paths: Vec<String>) -> Result<Vec<FileInfo>, AppError> {
+fn fetch_files_with_types_from_paths(paths: Vec<String>) -> Result<ScanReport, AppError> {
     get_files_with_types_from_paths(paths)
 }
 
@@ -45,24 +175,69 @@ fn get_file_stats(file_path: String) -> Result<FileStats, AppError> {
 }
 
 #[command]
-fn delete_all_unlisted_files(input_paths: Vec<String>, folder_path: String) -> Result<(), AppError> {
-    delete_unlisted_files(input_paths, folder_path)
+fn delete_all_unlisted_files(input_paths: Vec<String>, folder_path: String, dry_run: Option<bool>) -> Result<Vec<PathBuf>, AppError> {
+    delete_unlisted_files(input_paths, folder_path, dry_run.unwrap_or_default())
+}
+
+#[command]
+fn set_external_tool_path(tool: Tool, path: Option<String>) {
+    set_tool_path(tool, path.map(PathBuf::from));
+}
+
+#[command]
+fn locate_external_tool(tool: Tool) -> Result<String, AppError> {
+    Ok(resolve_tool(tool)?.to_string_lossy().to_string())
 }
 
 fn main() {
+  let workers = std::thread::available_parallelism().map_or(4, |count| count.get());
+  let job_manager = JobManager::new(workers).expect("failed to start the job worker pool");
+
   tauri::Builder::default()
     .plugin(tauri_plugin_store::Builder::default().build())
     .plugin(tauri_plugin_sql::Builder::default().build())
+    .manage(WatcherState(Mutex::new(None)))
+    .manage(job_manager)
+    .setup(|app| {
+        if let Some(resource_dir) = app.path_resolver().resource_dir() {
+            set_resource_dir(resource_dir);
+        }
+        Ok(())
+    })
     .invoke_handler(tauri::generate_handler![
         hash_file, 
         hash_files_in_directory, 
+        find_duplicates,
+        perceptual_hash_image,
+        find_similar_images,
+        find_similar_videos,
+        write_checksum_manifest,
+        verify_checksum_manifest,
+        relink_missing,
         fetch_files_with_types, 
+        fetch_scan_delta,
+        commit_scan,
+        forget_scanned_file_paths,
+        start_storehouse_watcher,
+        stop_storehouse_watcher,
+        submit_job,
+        cancel_job,
+        get_job_status,
         create_thumbnail_for_file, 
+        create_thumbnail_with_preview_for_file,
+        create_storyboard,
+        create_thumbnail_set_for_file,
+        get_cached_thumbnail_set,
+        move_thumbnails,
+        release_thumbnails,
+        collect_thumbnail_cache_garbage,
         copy_file_to_dir,
         fetch_files_with_types_from_paths, 
         get_video_frame_rate, 
         get_file_stats, 
-        delete_all_unlisted_files
+        delete_all_unlisted_files,
+        set_external_tool_path,
+        locate_external_tool
         ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
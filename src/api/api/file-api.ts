@@ -1,15 +1,32 @@
+import Database from "tauri-plugin-sql-api";
 import { DatabaseManager } from "../database/database-manager";
 import apiSlice from "../api-slice";
-import { createThumbnail, FileAndTypeInfo, getFilesAndTypes } from "./rust-api";
+import {
+  commitScan,
+  createThumbnail,
+  FileAndTypeInfo,
+  forgetScannedFiles,
+  getCachedThumbnailSet,
+  getScanDelta,
+  MissingFile,
+  moveCachedThumbnails,
+  relinkMissing,
+  RelinkProposal,
+  RelinkReport,
+  releaseThumbnails,
+  ScanError,
+  ThumbnailOptions,
+} from "./rust-api";
 import {
   getCoverAndStoreSetUp,
   getExistingFilePaths,
   getUniqueNameInFolder,
-  getUniqueNamesInFolder,
+  hashFilesInBackground,
 } from "./helper";
 import { removeDuplicates } from "@/lib/collection-utils";
 import { selectOne, selectOneOrNull } from "./database-helper";
 import { exists, removeFile } from "@tauri-apps/api/fs";
+import { getSettings } from "./settings-api";
 
 export const fileTypes = [
   "Audio",
@@ -120,6 +137,10 @@ export interface GetFilesResponse {
   totalPages: number;
 }
 
+export interface FindRelinksRequest {
+  searchRoot: string;
+}
+
 export interface UpdateFileRequest extends Partial<FileDetails> {
   id: number;
 }
@@ -155,6 +176,9 @@ export interface ScanFilesRequest {}
 export interface ScanFilesResponse {
   results: FileCommon[];
   failedCount: number;
+  scanErrors: ScanError[];
+  // Indexed files that disappeared, kept so they can be relinked
+  missingPaths: string[];
 }
 
 export interface CreateCompositeFileRequest {
@@ -179,6 +203,20 @@ export interface TagFileRequest {
   filePaths: string[];
 }
 
+const deleteFileRecord = async (db: Database, id: number) => {
+  await db.execute(`DELETE FROM LibraryIncludeFile WHERE file_id = ?`, [id]);
+  await db.execute(`DELETE FROM LibraryExcludeFile WHERE file_id = ?`, [id]);
+  await db.execute(`DELETE FROM FileTag WHERE file_id = ?`, [id]);
+  await db.execute(`DELETE FROM FileComposition WHERE composite_file_id = ?`, [
+    id,
+  ]);
+  await db.execute(`DELETE FROM FileData WHERE id = ?`, [id]);
+};
+
+// Covers of storehouse files live in the thumbnail cache of the cover folder,
+// shared by identical files
+const fileCoverOptions: ThumbnailOptions = { profiles: ["Detail"] };
+
 export const fileApi = apiSlice.injectEndpoints({
   endpoints: (builder) => ({
     getAllFiles: builder.query<GetFilesResponse, GetFilesRequest>({
@@ -425,33 +463,67 @@ export const fileApi = apiSlice.injectEndpoints({
           const { coverPath: cover_dir_path, storehousePaths: scan_dir_path } =
             await getCoverAndStoreSetUp();
 
+          const { indexPath, hashAlgorithm } = await getSettings();
+          const delta = await getScanDelta(scan_dir_path, indexPath!);
+
+          // Keep tags of files that were moved inside the storehouse
+          for (const renamed of delta.renamed) {
+            await db.execute(
+              `
+                UPDATE FileData
+                SET path = ?
+                WHERE path = ?
+              `,
+              [renamed.to.path, renamed.from],
+            );
+          }
+          await moveCachedThumbnails(
+            cover_dir_path,
+            delta.renamed.map((renamed) => [renamed.from, renamed.to.path]),
+          );
+
+          // Files that still exist are no longer media and leave the index,
+          // missing ones stay so they can be relinked
+          const missingPaths: string[] = [];
+          const noLongerMedia: string[] = [];
+          for (const path of delta.removed) {
+            if (await exists(path)) {
+              noLongerMedia.push(path);
+            } else {
+              missingPaths.push(path);
+            }
+          }
+          for (const path of noLongerMedia) {
+            const files: { id: number }[] = await db.select(
+              `SELECT id FROM FileData WHERE path = ?`,
+              [path],
+            );
+            for (const file of files) {
+              await deleteFileRecord(db, file.id);
+            }
+          }
+          await releaseThumbnails(cover_dir_path, noLongerMedia);
+
           // Get existing file paths
           const skipPaths = await getExistingFilePaths(db);
 
-          const filesAndTypes = await getFilesAndTypes(scan_dir_path);
-
-          const newFiles = filesAndTypes.filter(
+          const newFiles = delta.added.filter(
             (file) => !skipPaths.includes(file.path),
           );
 
           const failedFiles: FileAndTypeInfo[] = [];
           const newFileData: FileDetailsWithId[] = [];
-          const uniqueNames = await getUniqueNamesInFolder(
-            cover_dir_path,
-            newFiles.length,
-          );
-          let i = 0;
 
           for (const file of newFiles) {
             try {
-              const uniqueName = uniqueNames[i++];
-              const thumbnailPath = await createThumbnail(
-                uniqueName,
+              const thumbnails = await getCachedThumbnailSet(
                 file.path,
                 cover_dir_path,
                 1,
                 null,
+                { ...fileCoverOptions, cover_selection: "Smart" },
               );
+              const thumbnailPath = thumbnails?.Detail ?? null;
 
               newFileData.push({
                 id: 0,
@@ -490,9 +562,32 @@ export const fileApi = apiSlice.injectEndpoints({
             );
           }
 
+          // Changed contents invalidate the stored hash as well
+          void hashFilesInBackground(
+            db,
+            [
+              ...newFileData.map((file) => file.path),
+              ...delta.modified.map((file) => file.path),
+            ],
+            hashAlgorithm ?? "Sha256",
+          ).catch((error) => console.error("Background hashing failed", error));
+
+          await commitScan(scan_dir_path, indexPath!);
+          if (failedFiles.length > 0) {
+            await forgetScannedFiles(
+              scan_dir_path,
+              indexPath!,
+              failedFiles.map((file) => file.path),
+            );
+          }
+
           const report = {
             results: newFileData,
             failedCount: failedFiles.length,
+            scanErrors: delta.errors,
+            missingPaths: missingPaths.filter((path) =>
+              skipPaths.includes(path),
+            ),
           } as ScanFilesResponse;
 
           return {
@@ -506,6 +601,73 @@ export const fileApi = apiSlice.injectEndpoints({
       },
       invalidatesTags: [{ type: "FILE", id: "LIST" }],
     }),
+    // Looks for indexed files that no longer exist under a new location by their stored hash
+    findRelinks: builder.mutation<RelinkReport, FindRelinksRequest>({
+      queryFn: async ({ searchRoot }) => {
+        try {
+          const db = await DatabaseManager.getInstance().getDbInstance();
+          const files: { path: string; rsa: string }[] = await db.select(
+            `
+              SELECT path, rsa
+              FROM FileData
+              WHERE path IS NOT NULL AND rsa IS NOT NULL
+            `,
+            [],
+          );
+
+          const missing: MissingFile[] = [];
+          const skipPaths: string[] = [];
+          for (const file of files) {
+            if (await exists(file.path)) {
+              skipPaths.push(file.path);
+            } else {
+              missing.push({ path: file.path, expected_hash: file.rsa });
+            }
+          }
+
+          const { indexPath } = await getSettings();
+          const report = await relinkMissing(
+            missing,
+            searchRoot,
+            indexPath,
+            skipPaths,
+          );
+          return { data: report };
+        } catch (error: unknown) {
+          return Promise.reject({
+            message: (error as Error).message || "Failed to find moved files",
+          });
+        }
+      },
+    }),
+    applyRelinks: builder.mutation<null, RelinkProposal[]>({
+      queryFn: async (proposals) => {
+        try {
+          const db = await DatabaseManager.getInstance().getDbInstance();
+          for (const proposal of proposals) {
+            await db.execute(
+              `
+                UPDATE FileData
+                SET path = ?
+                WHERE path = ?
+              `,
+              [proposal.to, proposal.from],
+            );
+          }
+          const { coverPath: cover_dir_path } = await getCoverAndStoreSetUp();
+          await moveCachedThumbnails(
+            cover_dir_path,
+            proposals.map((proposal) => [proposal.from, proposal.to]),
+          );
+          return { data: null };
+        } catch (error: unknown) {
+          return Promise.reject({
+            message: (error as Error).message || "Failed to relink files",
+          });
+        }
+      },
+      invalidatesTags: [{ type: "FILE", id: "LIST" }],
+    }),
     updateFile: builder.mutation<null, UpdateFileRequest>({
       queryFn: async (request) => {
         try {
@@ -578,14 +740,14 @@ export const fileApi = apiSlice.injectEndpoints({
         try {
           const { id, videoFilePath, time } = request;
           const { coverPath: cover_dir_path } = await getCoverAndStoreSetUp();
-          const uniqueName = await getUniqueNameInFolder(cover_dir_path);
-          const thumbnailPath = await createThumbnail(
-            uniqueName,
+          const thumbnails = await getCachedThumbnailSet(
             videoFilePath,
             cover_dir_path,
             null,
             time,
+            fileCoverOptions,
           );
+          const thumbnailPath = thumbnails?.Detail ?? null;
 
           // Update the file's coverPath in the database
           const db = await DatabaseManager.getInstance().getDbInstance();
@@ -628,21 +790,12 @@ export const fileApi = apiSlice.injectEndpoints({
             if (existsPath) {
               await removeFile(path);
             }
+            const { coverPath: cover_dir_path } = await getCoverAndStoreSetUp();
+            await releaseThumbnails(cover_dir_path, [path]);
           }
 
           // Continue with the database deletions
-          await db.execute(`DELETE FROM LibraryIncludeFile WHERE file_id = ?`, [
-            id,
-          ]);
-          await db.execute(`DELETE FROM LibraryExcludeFile WHERE file_id = ?`, [
-            id,
-          ]);
-          await db.execute(`DELETE FROM FileTag WHERE file_id = ?`, [id]);
-          await db.execute(
-            `DELETE FROM FileComposition WHERE composite_file_id = ?`,
-            [id],
-          );
-          await db.execute(`DELETE FROM FileData WHERE id = ?`, [id]);
+          await deleteFileRecord(db, id);
 
           return { data: null };
         } catch (error: unknown) {
@@ -661,6 +814,15 @@ export const fileApi = apiSlice.injectEndpoints({
       queryFn: async () => {
         try {
           const db = await DatabaseManager.getInstance().getDbInstance();
+          const files: { path: string }[] = await db.select(
+            `SELECT path FROM FileData WHERE path IS NOT NULL`,
+          );
+          const { coverPath: cover_dir_path } = await getCoverAndStoreSetUp();
+          await releaseThumbnails(
+            cover_dir_path,
+            files.map((file) => file.path),
+          );
+
           await db.execute(`DELETE FROM LibraryIncludeFile`);
           await db.execute(`DELETE FROM LibraryExcludeFile`);
           await db.execute(`DELETE FROM FileTag`);
@@ -953,4 +1115,6 @@ export const {
   useUpdateTagsToFilesMutation,
   useUpdateCoverMutation,
   useTagFilesMutation,
+  useFindRelinksMutation,
+  useApplyRelinksMutation,
 } = fileApi;
@@ -1,7 +1,7 @@
 import apiSlice from "../api-slice";
 import { DatabaseManager } from "../database/database-manager";
 import { getCoverPath } from "./helper";
-import { deleteFilesInFolder } from "./rust-api";
+import { collectThumbnailCacheGarbage, deleteFilesInFolder } from "./rust-api";
 
 export const helperApi = apiSlice.injectEndpoints({
   endpoints: (builder) => ({
@@ -38,8 +38,11 @@ export const helperApi = apiSlice.injectEndpoints({
           // Define the folder path (adjust as needed)
           const folderPath = await getCoverPath();
 
-          // Call deleteFilesInFolder
-          await deleteFilesInFolder(folderPath, coverPaths);
+          // Call deleteFilesInFolder, which refuses an empty list of covers to keep
+          if (coverPaths.length > 0) {
+            await deleteFilesInFolder(folderPath, coverPaths);
+          }
+          await collectThumbnailCacheGarbage(folderPath);
 
           return { data: null };
         } catch (error: unknown) {
@@ -1,7 +1,14 @@
 import Database from "tauri-plugin-sql-api";
 import { v4 as uuidv4 } from "uuid";
 import { convertFileSrc } from "@tauri-apps/api/tauri";
-import { getFilesAndTypes } from "./rust-api";
+import {
+  fileHashResult,
+  getFilesAndTypes,
+  HashAlgorithm,
+  HashBatchReport,
+  submitJob,
+  waitForJob,
+} from "./rust-api";
 import { formatFileName } from "@/lib/format-utils";
 import { getSettings, GlobalSettings } from "./settings-api";
 
@@ -59,6 +66,63 @@ export const getExistingFilePaths = async (db: Database) => {
   return existingFiles.map((file) => file.path);
 };
 
+// The rsa column keeps the algorithm next to the hash so stored values stay comparable
+export const formatStoredHash = ({ algorithm, hash }: fileHashResult) => {
+  return `${algorithm}:${hash}`;
+};
+
+// Full hashes of large files take minutes, so they are filled in after the scan
+export const hashFilesInBackground = async (
+  db: Database,
+  paths: string[],
+  algorithm: HashAlgorithm,
+) => {
+  if (paths.length === 0) return;
+
+  const jobId = await submitJob({
+    kind: "HashBatch",
+    paths,
+    algorithm,
+    background: true,
+  });
+  const status = await waitForJob(jobId);
+  if (status.state !== "Completed") return;
+
+  const report = status.result as HashBatchReport;
+  for (const result of report.results) {
+    await db.execute(
+      `
+      UPDATE FileData
+      SET rsa = ?
+      WHERE path = ?
+      `,
+      [formatStoredHash(result), result.path],
+    );
+  }
+};
+
+// Re-hashes every indexed file that has a stored hash and returns the `Verify` job id
+export const verifyIndexedFiles = async (
+  db: Database,
+  reportPath: string | null = null,
+) => {
+  const files: { path: string; rsa: string }[] = await db.select(
+    `
+    SELECT path, rsa
+    FROM FileData
+    WHERE rsa IS NOT NULL
+    `,
+    [],
+  );
+
+  return submitJob({
+    kind: "Verify",
+    items: files.map((file) => ({ path: file.path, expected_hash: file.rsa })),
+    report_path: reportPath ?? undefined,
+    background: true,
+  });
+};
+
 export const pathToUrl = (
   path: string | undefined | null,
 ): string | undefined => {
@@ -98,8 +162,8 @@ export const getUniqueNamesInFolder = async (
 };
 
 export const getFileNamesInFolder = async (dirPath: string) => {
-  const filesInfo = await getFilesAndTypes(dirPath);
-  return filesInfo.map((file) => formatFileName(file.name)!);
+  const { files } = await getFilesAndTypes(dirPath);
+  return files.map((file) => formatFileName(file.name)!);
 };
 
 export const getUniqueNameNotInList = async (existingNames: string[]) => {
@@ -1,14 +1,82 @@
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 
 export type FileAndTypeInfo = {
   path: string;
   name: string;
   file_type: string;
+  sniffed_mime: string | null;
+  extension_mime: string | null;
 };
 
+// Rejection value of every command. `code` is stable and safe to branch on.
+export type AppError = {
+  code:
+    | "NotFound"
+    | "PermissionDenied"
+    | "InvalidPath"
+    | "InvalidInput"
+    | "UnsupportedType"
+    | "Decode"
+    | "ToolMissing"
+    | "ToolFailed"
+    | "Io"
+    | "Cancelled"
+    | "Internal";
+  message: string;
+  path: string | null;
+  // Set for transient failures that may succeed when tried again
+  retryable: boolean;
+  tool?: string;
+  exit_code?: number | null;
+  stderr?: string;
+};
+
+export const isAppError = (error: unknown): error is AppError =>
+  typeof error === "object" &&
+  error !== null &&
+  typeof (error as AppError).code === "string" &&
+  typeof (error as AppError).message === "string";
+
+export type ScanError = {
+  path: string;
+  kind:
+    | "PermissionDenied"
+    | "NotFound"
+    | "SymlinkLoop"
+    | "InvalidPath"
+    | "IgnoreRule"
+    | "Decode"
+    | "Io";
+  message: string;
+};
+
+export type ScanReport = {
+  files: FileAndTypeInfo[];
+  errors: ScanError[];
+  skipped: number;
+};
+
+// Omitted fields use the backend defaults
+export type ScanOptions = {
+  max_depth?: number | null;
+  include_hidden?: boolean;
+  follow_symlinks?: boolean;
+  min_file_size?: number;
+  include_globs?: string[];
+  exclude_globs?: string[];
+};
+
+export type HashAlgorithm = "Sha256" | "Blake3" | "Xxh3_128";
+
+// Sampled hashes only cover the size and a few chunks and never equal a full hash
+export type HashMode = "Full" | "Sampled";
+
 export type fileHashResult = {
   path: string;
   hash: string;
+  algorithm: HashAlgorithm;
+  mode: HashMode;
 };
 
 export type fileStatsResult = {
@@ -34,47 +102,331 @@ export const getVideoFrameRate = async (filePath: string): Promise<number> => {
 
 export const getFilesAndTypes = async (
   dirPath: string,
-): Promise<FileAndTypeInfo[]> => {
-  const results: FileAndTypeInfo[] = await invoke("fetch_files_with_types", {
+  options: ScanOptions | null = null,
+): Promise<ScanReport> => {
+  const report: ScanReport = await invoke("fetch_files_with_types", {
     dir: dirPath,
+    options,
   });
-  return results;
+  return report;
+};
+
+export type RenamedFileInfo = {
+  from: string;
+  to: FileAndTypeInfo;
+};
+
+export type ScanDelta = {
+  added: FileAndTypeInfo[];
+  modified: FileAndTypeInfo[];
+  removed: string[];
+  renamed: RenamedFileInfo[];
+  errors: ScanError[];
+};
+
+export const getScanDelta = async (
+  dirPath: string,
+  indexDir: string,
+  options: ScanOptions | null = null,
+): Promise<ScanDelta> => {
+  const delta: ScanDelta = await invoke("fetch_scan_delta", {
+    dir: dirPath,
+    indexDir,
+    options,
+  });
+  return delta;
+};
+
+// Marks the last delta of the storehouse as applied, until then rescans report it again
+export const commitScan = async (
+  dirPath: string,
+  indexDir: string,
+): Promise<void> => {
+  await invoke("commit_scan", { dir: dirPath, indexDir });
+};
+
+export const forgetScannedFiles = async (
+  dirPath: string,
+  indexDir: string,
+  paths: string[],
+): Promise<void> => {
+  await invoke("forget_scanned_file_paths", {
+    dir: dirPath,
+    indexDir,
+    paths,
+  });
+};
+
+export type RemovedFileInfo = {
+  path: string;
+  // Guessed from the extension
+  file_type: string | null;
+};
+
+// Payloads of the events emitted while the storehouse is watched
+export type StorehouseWatchEvents = {
+  "file-added": FileAndTypeInfo;
+  "file-modified": FileAndTypeInfo;
+  // Sent once per file, also when a whole directory was removed
+  "file-removed": RemovedFileInfo;
+  "file-renamed": RenamedFileInfo;
+  "watch-error": AppError;
+};
+
+// Reports the files a scan with the same options would index
+export const startStorehouseWatcher = async (
+  dirPath: string,
+  options: ScanOptions | null = null,
+): Promise<void> => {
+  await invoke("start_storehouse_watcher", { dir: dirPath, options });
+};
+
+export const stopStorehouseWatcher = async (): Promise<void> => {
+  await invoke("stop_storehouse_watcher");
 };
 
 export const getFilesAndTypesWithPaths = async (
   paths: string[],
-): Promise<FileAndTypeInfo[]> => {
-  const results: FileAndTypeInfo[] = await invoke(
+): Promise<ScanReport> => {
+  const report: ScanReport = await invoke(
     "fetch_files_with_types_from_paths",
     {
       paths,
     },
   );
-  return results;
+  return report;
 };
 
-export const hashFile = async (filePath: string): Promise<fileHashResult> => {
-  const result: fileHashResult = await invoke("hash_file", { filePath });
+export const hashFile = async (
+  filePath: string,
+  algorithm: HashAlgorithm | null = null,
+  mode: HashMode | null = null,
+): Promise<fileHashResult> => {
+  const result: fileHashResult = await invoke("hash_file", {
+    filePath,
+    algorithm,
+    mode,
+  });
   return result;
 };
 
+export type HashBatchReport = {
+  results: fileHashResult[];
+  errors: ScanError[];
+};
+
 export const hashFilesInDirectory = async (
   dirPath: string,
   skipPaths: string[],
-): Promise<fileHashResult[]> => {
-  const results: fileHashResult[] = await invoke("hash_files_in_directory", {
+  options: ScanOptions | null = null,
+  algorithm: HashAlgorithm | null = null,
+  mode: HashMode | null = null,
+): Promise<HashBatchReport> => {
+  const report: HashBatchReport = await invoke("hash_files_in_directory", {
     dirPath,
     skipPaths,
+    options,
+    algorithm,
+    mode,
   });
-  return results;
+  return report;
 };
 
+export type DuplicateGroup = {
+  size: number;
+  hash: string;
+  algorithm: HashAlgorithm;
+  paths: string[];
+  hard_links: string[];
+  reclaimable_bytes: number;
+};
+
+export type DuplicateReport = {
+  groups: DuplicateGroup[];
+  reclaimable_bytes: number;
+  errors: ScanError[];
+};
+
+export const findDuplicates = async (
+  dirPath: string,
+  options: ScanOptions | null = null,
+  algorithm: HashAlgorithm | null = null,
+): Promise<DuplicateReport> => {
+  const report: DuplicateReport = await invoke("find_duplicates", {
+    dirPath,
+    options,
+    algorithm,
+  });
+  return report;
+};
+
+export type PerceptualHashKind = "DHash" | "PHash";
+
+// Hashes are 64 bit values encoded as 16 hex digits
+export type PerceptualHashResult = {
+  path: string;
+  hash: string;
+  kind: PerceptualHashKind;
+};
+
+export const perceptualHashImage = async (
+  filePath: string,
+  kind: PerceptualHashKind | null = null,
+): Promise<PerceptualHashResult> => {
+  const result: PerceptualHashResult = await invoke("perceptual_hash_image", {
+    filePath,
+    kind,
+  });
+  return result;
+};
+
+export type SimilarImageGroup = {
+  images: { path: string; hash: string; width: number; height: number }[];
+  max_distance: number;
+};
+
+export type SimilarImagesReport = {
+  groups: SimilarImageGroup[];
+  errors: ScanError[];
+};
+
+export const findSimilarImages = async (
+  dirPath: string,
+  options: ScanOptions | null = null,
+  kind: PerceptualHashKind | null = null,
+  threshold: number | null = null,
+): Promise<SimilarImagesReport> => {
+  const report: SimilarImagesReport = await invoke("find_similar_images", {
+    dirPath,
+    options,
+    kind,
+    threshold,
+  });
+  return report;
+};
+
+export type SimilarVideoGroup = {
+  videos: { path: string; size: number; duration: number }[];
+  // Average Hamming distance per sampled frame
+  max_distance: number;
+};
+
+export type SimilarVideosReport = {
+  groups: SimilarVideoGroup[];
+  errors: ScanError[];
+};
+
+export const findSimilarVideos = async (
+  dirPath: string,
+  indexDir: string,
+  options: ScanOptions | null = null,
+  threshold: number | null = null,
+): Promise<SimilarVideosReport> => {
+  const report: SimilarVideosReport = await invoke("find_similar_videos", {
+    dirPath,
+    indexDir,
+    options,
+    threshold,
+  });
+  return report;
+};
+
+export type ManifestWriteReport = {
+  manifest_path: string;
+  algorithm: HashAlgorithm;
+  entries: number;
+  errors: ScanError[];
+};
+
+// Directories in `paths` are walked, files are listed as given. Without an
+// algorithm it is taken from the manifest name, e.g. SHA256SUMS or B3SUMS.
+export const writeChecksumManifest = async (
+  manifestPath: string,
+  paths: string[],
+  options: ScanOptions | null = null,
+  algorithm: HashAlgorithm | null = null,
+): Promise<ManifestWriteReport> => {
+  const report: ManifestWriteReport = await invoke("write_checksum_manifest", {
+    manifestPath,
+    paths,
+    options,
+    algorithm,
+  });
+  return report;
+};
+
+export type ManifestLineResult = {
+  line: number;
+  path: string | null;
+  status: "Ok" | "Failed" | "Missing" | "Unreadable" | "Malformed";
+  message: string | null;
+};
+
+export type ManifestVerifyReport = {
+  algorithm: HashAlgorithm;
+  lines: ManifestLineResult[];
+};
+
+export const verifyChecksumManifest = async (
+  manifestPath: string,
+  algorithm: HashAlgorithm | null = null,
+): Promise<ManifestVerifyReport> => {
+  const report: ManifestVerifyReport = await invoke(
+    "verify_checksum_manifest",
+    {
+      manifestPath,
+      algorithm,
+    },
+  );
+  return report;
+};
+
+export type MissingFile = {
+  path: string;
+  // `Algorithm:hex` as stored in the rsa column, or bare SHA-256 hex
+  expected_hash: string;
+};
+
+export type RelinkProposal = {
+  from: string;
+  to: string;
+  other_matches: string[];
+};
+
+export type RelinkReport = {
+  proposals: RelinkProposal[];
+  unresolved: string[];
+  errors: ScanError[];
+};
+
+export const relinkMissing = async (
+  missing: MissingFile[],
+  searchRoot: string,
+  indexDir: string | null,
+  skipPaths: string[],
+  options: ScanOptions | null = null,
+): Promise<RelinkReport> => {
+  const report: RelinkReport = await invoke("relink_missing", {
+    missing,
+    searchRoot,
+    indexDir,
+    skipPaths,
+    options,
+  });
+  return report;
+};
+
+// Smart samples frames across a video and keeps the sharpest well-exposed one,
+// falling back to `frameNumber`. A `time` always takes precedence.
+export type CoverSelection = "Fixed" | "Smart";
+
 export const createThumbnail = async (
   coverName: string,
   filePath: string,
   indexDir: string,
   frameNumber: number | null,
   time: number | null,
+  coverSelection: CoverSelection = "Fixed",
 ): Promise<string> => {
   const results: string = await invoke("create_thumbnail_for_file", {
     coverName,
@@ -82,15 +434,185 @@ export const createThumbnail = async (
     indexDir,
     frameNumber,
     time,
+    coverSelection,
   });
   return results;
 };
 
+export type PreviewOptions = {
+  segments?: number;
+  // Seconds of each segment
+  segment_duration?: number;
+  width?: number;
+  fps?: number;
+  format?: "WebP" | "Gif";
+};
+
+export type ThumbnailWithPreview = {
+  thumbnail_path: string | null;
+  // Silent looping clip next to the cover, null for images or when it failed
+  preview_path: string | null;
+  // Why the preview failed, the cover is still usable
+  preview_error: AppError | null;
+};
+
+export const createThumbnailWithPreview = async (
+  coverName: string,
+  filePath: string,
+  indexDir: string,
+  frameNumber: number | null,
+  time: number | null,
+  coverSelection: CoverSelection = "Fixed",
+  preview: PreviewOptions = {},
+): Promise<ThumbnailWithPreview> => {
+  const results: ThumbnailWithPreview = await invoke(
+    "create_thumbnail_with_preview_for_file",
+    {
+      coverName,
+      filePath,
+      indexDir,
+      frameNumber,
+      time,
+      coverSelection,
+      preview,
+    },
+  );
+  return results;
+};
+
+export type StoryboardOptions = {
+  // Seconds between frames, widened for long videos
+  interval?: number;
+  columns?: number;
+  tile_width?: number;
+  format?: ThumbnailFormat;
+};
+
+export type Storyboard = {
+  sprite_path: string;
+  // Cues map time ranges to `<sprite>#xywh=x,y,w,h`
+  vtt_path: string;
+  interval: number;
+  tile_width: number;
+  tile_height: number;
+  columns: number;
+  tiles: number;
+};
+
+// Resolves with null for files that are not videos
+export const createStoryboard = async (
+  name: string,
+  filePath: string,
+  indexDir: string,
+  options: StoryboardOptions = {},
+): Promise<Storyboard | null> => {
+  const storyboard: Storyboard | null = await invoke("create_storyboard", {
+    name,
+    filePath,
+    indexDir,
+    options,
+  });
+  return storyboard;
+};
+
+export type ThumbnailProfile = "Grid" | "Cover" | "Detail";
+
+export type ThumbnailFormat = "Jpeg" | "WebP" | "Avif";
+
+// Grid fits 256px, Cover is a 512px square crop, Detail fits 1024px
+export type ThumbnailOptions = {
+  // Every profile when empty or omitted
+  profiles?: ThumbnailProfile[];
+  format?: ThumbnailFormat;
+  cover_selection?: CoverSelection;
+};
+
+export type ThumbnailSet = Partial<Record<ThumbnailProfile, string>>;
+
+// Resolves with null for files that are neither images nor videos
+export const createThumbnailSet = async (
+  coverName: string,
+  filePath: string,
+  indexDir: string,
+  frameNumber: number | null,
+  time: number | null,
+  options: ThumbnailOptions = {},
+): Promise<ThumbnailSet | null> => {
+  const thumbnails: ThumbnailSet | null = await invoke(
+    "create_thumbnail_set_for_file",
+    {
+      coverName,
+      filePath,
+      indexDir,
+      frameNumber,
+      time,
+      options,
+    },
+  );
+  return thumbnails;
+};
+
+// Thumbnails stored under the content hash of the file in `<cacheDir>/thumbnails`,
+// shared by identical files and only generated again when the file changes
+export const getCachedThumbnailSet = async (
+  filePath: string,
+  cacheDir: string,
+  frameNumber: number | null,
+  time: number | null,
+  options: ThumbnailOptions = {},
+): Promise<ThumbnailSet | null> => {
+  const thumbnails: ThumbnailSet | null = await invoke(
+    "get_cached_thumbnail_set",
+    {
+      filePath,
+      cacheDir,
+      frameNumber,
+      time,
+      options,
+    },
+  );
+  return thumbnails;
+};
+
+// Call for files moved inside the storehouse so they keep their thumbnails
+export const moveCachedThumbnails = async (
+  cacheDir: string,
+  moves: [string, string][],
+): Promise<void> => {
+  await invoke("move_thumbnails", { cacheDir, moves });
+};
+
+// Call for files removed from the storehouse so their thumbnails can be collected
+export const releaseThumbnails = async (
+  cacheDir: string,
+  paths: string[],
+): Promise<void> => {
+  await invoke("release_thumbnails", { cacheDir, paths });
+};
+
+// Resolves with the deleted thumbnails, those no file references any more
+export const collectThumbnailCacheGarbage = async (
+  cacheDir: string,
+): Promise<string[]> => {
+  const deleted: string[] = await invoke("collect_thumbnail_cache_garbage", {
+    cacheDir,
+  });
+  return deleted;
+};
+
+// Resolves with the deleted files, or with `dryRun` the files that would be
+// deleted. Rejects an empty `inputPaths` rather than emptying the folder.
 export const deleteFilesInFolder = async (
   folderPath: string,
   inputPaths: string[],
-): Promise<void> => {
-  await invoke("delete_all_unlisted_files", { inputPaths, folderPath });
+  dryRun = false,
+): Promise<string[]> => {
+  const deleted: string[] = await invoke("delete_all_unlisted_files", {
+    inputPaths,
+    folderPath,
+    dryRun,
+  });
+  return deleted;
 };
 
 export const copyFileToDir = async (
@@ -103,3 +625,127 @@ export const copyFileToDir = async (
   });
   return destPath;
 };
+
+export type ExternalTool = "ffmpeg" | "ffprobe" | "magick";
+
+export const setExternalToolPath = async (
+  tool: ExternalTool,
+  path: string | null,
+): Promise<void> => {
+  await invoke("set_external_tool_path", { tool, path });
+};
+
+export const locateExternalTool = async (
+  tool: ExternalTool,
+): Promise<string> => {
+  const toolPath: string = await invoke("locate_external_tool", { tool });
+  return toolPath;
+};
+
+export type ThumbnailRequest = {
+  cover_name: string;
+  file_path: string;
+  frame_number: number | null;
+  time: number | null;
+  cover_selection?: CoverSelection;
+};
+
+// Result of a finished `ThumbnailBatch` job, one per item
+export type ThumbnailResult = {
+  cover_name: string;
+  file_path: string;
+  thumbnail_path: string | null;
+  error: AppError | null;
+};
+
+export type VerifyItem = {
+  path: string;
+  // `Algorithm:hex` as stored in the rsa column, or bare SHA-256 hex
+  expected_hash: string;
+};
+
+// Result of a finished `Verify` job
+export type VerifyReport = {
+  verified: number;
+  mismatched: {
+    path: string;
+    algorithm: HashAlgorithm;
+    expected: string;
+    actual: string;
+  }[];
+  missing: string[];
+  unreadable: ScanError[];
+};
+
+export type JobRequest =
+  | { kind: "Scan"; dir: string; options?: ScanOptions }
+  | {
+      kind: "HashBatch";
+      paths: string[];
+      algorithm?: HashAlgorithm;
+      mode?: HashMode;
+      background?: boolean;
+    }
+  | { kind: "ThumbnailBatch"; index_dir: string; items: ThumbnailRequest[] }
+  | {
+      kind: "Verify";
+      items: VerifyItem[];
+      report_path?: string;
+      background?: boolean;
+    };
+
+export type JobState = "Running" | "Completed" | "Failed" | "Cancelled";
+
+// Payload of the `job-progress` event
+export type JobProgress = {
+  id: number;
+  kind: JobRequest["kind"];
+  state: JobState;
+  done: number;
+  total: number;
+  current_path: string | null;
+};
+
+export type JobStatus = JobProgress & {
+  result: unknown | null;
+  error: AppError | null;
+};
+
+export const submitJob = async (request: JobRequest): Promise<number> => {
+  const id: number = await invoke("submit_job", { request });
+  return id;
+};
+
+export const cancelJob = async (id: number): Promise<void> => {
+  await invoke("cancel_job", { id });
+};
+
+export const getJobStatus = async (id: number): Promise<JobStatus> => {
+  const status: JobStatus = await invoke("get_job_status", { id });
+  return status;
+};
+
+// Resolves with the final status once the job is no longer running
+export const waitForJob = async (id: number): Promise<JobStatus> => {
+  let notifyFinished = () => {};
+  const finished = new Promise<void>((resolve) => {
+    notifyFinished = resolve;
+  });
+  const unlisten = await listen<JobProgress>("job-progress", ({ payload }) => {
+    if (payload.id === id && payload.state !== "Running") {
+      notifyFinished();
+    }
+  });
+
+  try {
+    // The job may have finished before the listener was registered
+    let status = await getJobStatus(id);
+    if (status.state === "Running") {
+      await finished;
+      status = await getJobStatus(id);
+    }
+    return status;
+  } finally {
+    unlisten();
+  }
+};
@@ -1,10 +1,12 @@
 import { Store } from "tauri-plugin-store-api";
 import apiSlice from "../api-slice";
+import { HashAlgorithm } from "./rust-api";
 
 export type GlobalSettings = {
   // setup settings
   indexPath: string | null;
   storehousePath: string | null;
+  hashAlgorithm: HashAlgorithm;
   // video settings
   volume: number;
 };
@@ -14,6 +16,7 @@ export type PartialGlobalSettings = Partial<GlobalSettings>;
 const DEFAULT_SETTINGS: GlobalSettings = {
   indexPath: null,
   storehousePath: null,
+  hashAlgorithm: "Sha256",
   volume: 1,
 };
 